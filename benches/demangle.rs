@@ -0,0 +1,78 @@
+//! Measures time and heap allocations per symbol for the demangling
+//! pipeline, using the symbols from the test data. Run with
+//! `cargo bench --bench demangle`.
+
+extern crate std_mangle_rs;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use std_mangle_rs::{ast_to_demangled_symbol, demangle_into, demangle_symbol, mangled_symbol_to_ast};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const ITERATIONS: usize = 10_000;
+
+fn main() {
+    let test_data = include_str!("../src/demangling_test_data.txt");
+    let symbols: Vec<&str> = test_data
+        .lines()
+        .filter(|line| line.starts_with("_R") && !line.contains(" !error"))
+        .map(|line| line.split_whitespace().next().unwrap())
+        .collect();
+
+    bench("parse", &symbols, |symbol| {
+        mangled_symbol_to_ast(symbol).unwrap();
+    });
+
+    bench("parse + demangle", &symbols, |symbol| {
+        ast_to_demangled_symbol(&mangled_symbol_to_ast(symbol).unwrap());
+    });
+
+    bench("streaming demangle", &symbols, |symbol| {
+        let mut out = String::new();
+        demangle_symbol(symbol, &mut out).unwrap();
+    });
+
+    bench("demangle into buffer", &symbols, |symbol| {
+        let mut buf = [0; 1024];
+        demangle_into(symbol.as_bytes(), &mut buf).unwrap();
+    });
+}
+
+fn bench<F: Fn(&str)>(name: &str, symbols: &[&str], f: F) {
+    let allocations_before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+
+    for _ in 0 .. ITERATIONS {
+        for symbol in symbols {
+            f(symbol);
+        }
+    }
+
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations_before;
+    let runs = (ITERATIONS * symbols.len()) as f64;
+
+    println!("{:<20} {:>8.1} allocations/symbol {:>10.1} ns/symbol",
+             name,
+             allocations as f64 / runs,
+             elapsed.as_nanos() as f64 / runs);
+}
@@ -14,7 +14,7 @@ fn main() {
 
     let lines: Vec<_> = test_case_definitions.lines().map(|l| l.unwrap()).collect();
 
-    for i in 1..lines.len() - 1 {
+    for i in 1..lines.len() {
         if lines[i].starts_with("_R") && lines[i - 1].starts_with("#") {
             let title_line = &lines[i - 1];
             let spec_line = &lines[i];
@@ -37,6 +37,13 @@ fn emit_test_case(spec_line: &str, title_line: &str, output: &mut impl Write) {
             .replace("-", "_");
 
         writeln!(output, "#[test] #[allow(non_snake_case)] fn {}() {{", title).unwrap();
+
+        if let Some(expected_error) = demangled.strip_prefix("!error") {
+            emit_error_test_body(mangled, expected_error, output);
+            writeln!(output, "}}").unwrap();
+            return;
+        }
+
         writeln!(output, "  let demangled_expected = r#\"{}\"#;", demangled).unwrap();
         writeln!(
             output,
@@ -51,6 +58,69 @@ fn emit_test_case(spec_line: &str, title_line: &str, output: &mut impl Write) {
             output,
             "  assert_eq!(demangled_expected, demangled_actual);"
         ).unwrap();
+
+        // The streaming demangler must agree with the AST
+        writeln!(output, "  let mut streamed = ::alloc::string::String::new();").unwrap();
+        writeln!(
+            output,
+            "  ::demangle_symbol(r#\"{}\"#, &mut streamed).unwrap();",
+            mangled
+        ).unwrap();
+        writeln!(output, "  assert_eq!(demangled_expected, streamed);").unwrap();
+
+        // Mangling the AST again must lead back to the same AST, and to the
+        // same bytes exactly if the symbol is encoded canonically
+        writeln!(output, "  let mangled_again = ::ast_to_mangled_symbol(&ast).unwrap();").unwrap();
+        writeln!(
+            output,
+            "  assert_eq!(Ok(&ast), ::mangled_symbol_to_ast(&mangled_again).as_ref());"
+        ).unwrap();
+        writeln!(
+            output,
+            "  assert_eq!(::validate::validate(br#\"{}\"#).unwrap().is_empty(), \
+                 r#\"{}\"# == mangled_again);",
+            mangled,
+            mangled
+        ).unwrap();
         writeln!(output, "}}").unwrap();
     }
 }
+
+/// Emits the checks for `!error at <position>: <kind>`: the parser must
+/// reject the symbol at that position with an error of that kind, and the
+/// streaming demangler must reject it at the same position.
+fn emit_error_test_body(mangled: &str, expected_error: &str, output: &mut impl Write) {
+    let expected_error = expected_error.trim();
+    let expected_error = expected_error.strip_prefix("at ")
+        .unwrap_or_else(|| panic!("malformed error spec: {}", expected_error));
+
+    let (position, kind) = expected_error.split_once(':')
+        .unwrap_or_else(|| panic!("error spec without a kind: {}", expected_error));
+    let position: usize = position.trim().parse().unwrap();
+
+    writeln!(
+        output,
+        "  let error = ::parse::parse_with_context(br#\"{}\"#).unwrap_err();",
+        mangled
+    ).unwrap();
+    writeln!(
+        output,
+        "  assert_eq!(error.position, {}, \"{{}}\", error);",
+        position
+    ).unwrap();
+    writeln!(
+        output,
+        "  assert_eq!(error.kind, ::parse::ErrorKind::{}, \"{{}}\", error);",
+        kind.trim()
+    ).unwrap();
+    writeln!(
+        output,
+        "  let error = ::demangle_symbol(r#\"{}\"#, &mut ::alloc::string::String::new()).unwrap_err();",
+        mangled
+    ).unwrap();
+    writeln!(
+        output,
+        "  assert!(error.starts_with(\"at position {}: \"), \"{{}}\", error);",
+        position
+    ).unwrap();
+}
@@ -0,0 +1,16 @@
+extern crate cc;
+
+use std::env;
+
+fn main() {
+    // The C test is only linked into the unit tests, see `tests::c`
+    cc::Build::new()
+        .file("tests/demangle.c")
+        .include("include")
+        .cargo_metadata(false)
+        .compile("std_mangle_c_tests");
+
+    println!("cargo:rustc-link-search=native={}", env::var("OUT_DIR").unwrap());
+    println!("cargo:rerun-if-changed=tests/demangle.c");
+    println!("cargo:rerun-if-changed=include/std_mangle.h");
+}
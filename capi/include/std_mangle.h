@@ -0,0 +1,49 @@
+#ifndef STD_MANGLE_H
+#define STD_MANGLE_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Status codes, following the ones of __cxa_demangle. */
+#define RUST_V0_DEMANGLE_SUCCESS 0
+/*
+ * The output buffer is too small, the output is longer than 1 MiB, or memory
+ * could not be allocated.
+ */
+#define RUST_V0_DEMANGLE_NO_MEMORY -1
+/* The input is not a valid v0 symbol name. */
+#define RUST_V0_DEMANGLE_INVALID_NAME -2
+/* One of the arguments is invalid. */
+#define RUST_V0_DEMANGLE_INVALID_ARGUMENT -3
+
+/*
+ * Demangles the NUL-terminated `mangled` into `out`, which has room for
+ * `out_len` bytes including the terminating NUL. `flags` is reserved and
+ * must be 0. If the output does not fit, or is longer than 1 MiB, `out` holds
+ * as much of it as does, NUL-terminated, and RUST_V0_DEMANGLE_NO_MEMORY is
+ * returned. Does not allocate, so it may be called from signal handlers.
+ */
+int rust_v0_demangle(const char *mangled, char *out, size_t out_len, int flags);
+
+/*
+ * Demangles the NUL-terminated `mangled` into memory obtained from malloc,
+ * like __cxa_demangle. If `output_buffer` is not NULL, it must have been
+ * allocated with malloc and be `*length` bytes long; it is used for the
+ * output and grown with realloc if needed. Returns the NUL-terminated
+ * output, which the caller has to free, or NULL on failure. If `length` is
+ * not NULL, the size of the returned buffer is stored in it. If `status` is
+ * not NULL, one of the RUST_V0_DEMANGLE_* status codes is stored in it.
+ */
+char *rust_v0_demangle_alloc(const char *mangled,
+                             char *output_buffer,
+                             size_t *length,
+                             int *status);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,310 @@
+//! C bindings for the demangler, declared in `include/std_mangle.h`. The
+//! status codes follow the ones of `__cxa_demangle`.
+
+extern crate std_mangle_rs;
+
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::{ptr, slice};
+use std_mangle_rs::demangle_into;
+use std_mangle_rs::stream_demangle::DemangleError;
+
+extern "C" {
+    fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void;
+}
+
+/// The symbol was demangled successfully.
+pub const RUST_V0_DEMANGLE_SUCCESS: c_int = 0;
+/// The output buffer is too small, the output is longer than
+/// `MAX_DEMANGLED_LEN`, or memory could not be allocated.
+pub const RUST_V0_DEMANGLE_NO_MEMORY: c_int = -1;
+/// The input is not a valid v0 symbol name.
+pub const RUST_V0_DEMANGLE_INVALID_NAME: c_int = -2;
+/// One of the arguments is invalid.
+pub const RUST_V0_DEMANGLE_INVALID_ARGUMENT: c_int = -3;
+
+/// Demangles the NUL-terminated `mangled` into `out`, which has room for
+/// `out_len` bytes including the terminating NUL. `flags` is reserved and
+/// must be 0. If the output does not fit, or is longer than
+/// `MAX_DEMANGLED_LEN`, `out` holds as much of it as does, NUL-terminated,
+/// and `RUST_V0_DEMANGLE_NO_MEMORY` is returned.
+/// Does not allocate.
+///
+/// # Safety
+///
+/// `mangled` must point to a NUL-terminated string and `out` to at least
+/// `out_len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rust_v0_demangle(mangled: *const c_char,
+                                          out: *mut c_char,
+                                          out_len: usize,
+                                          flags: c_int)
+                                          -> c_int {
+    if mangled.is_null() || out.is_null() || out_len == 0 || flags != 0 {
+        return RUST_V0_DEMANGLE_INVALID_ARGUMENT;
+    }
+
+    let mangled = CStr::from_ptr(mangled).to_bytes();
+    let out = slice::from_raw_parts_mut(out as *mut u8, out_len);
+
+    // Leave room for the NUL and make sure that truncated output is
+    // terminated, too.
+    for byte in out.iter_mut() {
+        *byte = 0;
+    }
+
+    match demangle_into(mangled, &mut out[.. out_len - 1]) {
+        Ok(_) => RUST_V0_DEMANGLE_SUCCESS,
+        Err(DemangleError::Truncated { .. }) |
+        Err(DemangleError::TooLong) => RUST_V0_DEMANGLE_NO_MEMORY,
+        Err(_) => RUST_V0_DEMANGLE_INVALID_NAME,
+    }
+}
+
+/// Demangles the NUL-terminated `mangled` into memory obtained from
+/// `malloc`, like `__cxa_demangle`. If `output_buffer` is not null, it must
+/// have been allocated with `malloc` and be `*length` bytes long; it is
+/// used for the output and grown with `realloc` if needed. Returns the
+/// NUL-terminated output, which the caller has to `free`, or null on
+/// failure. If `length` is not null, the size of the returned buffer is
+/// stored in it. If `status` is not null, one of the
+/// `RUST_V0_DEMANGLE_*` status codes is stored in it.
+///
+/// # Safety
+///
+/// `mangled` must point to a NUL-terminated string. `output_buffer`, if
+/// not null, must be a `malloc` allocation of `*length` bytes. `length`
+/// and `status` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rust_v0_demangle_alloc(mangled: *const c_char,
+                                                output_buffer: *mut c_char,
+                                                length: *mut usize,
+                                                status: *mut c_int)
+                                                -> *mut c_char {
+    let (result, code) = demangle_alloc(mangled, output_buffer, length);
+
+    if !status.is_null() {
+        *status = code;
+    }
+
+    result
+}
+
+unsafe fn demangle_alloc(mangled: *const c_char,
+                         output_buffer: *mut c_char,
+                         length: *mut usize)
+                         -> (*mut c_char, c_int) {
+    if mangled.is_null() || (!output_buffer.is_null() && length.is_null()) {
+        return (ptr::null_mut(), RUST_V0_DEMANGLE_INVALID_ARGUMENT);
+    }
+
+    let mangled = CStr::from_ptr(mangled).to_bytes();
+    let (mut buffer, mut buffer_len) = if output_buffer.is_null() {
+        (ptr::null_mut(), 0)
+    } else {
+        (output_buffer, *length)
+    };
+
+    // Only demangle a second time if the output does not fit. Without a
+    // buffer, the first pass just finds out how much room the output needs.
+    let len = match demangle_into(mangled, output_slice(buffer, buffer_len)) {
+        Ok(len) => len,
+        Err(DemangleError::Truncated { required }) => {
+            buffer = realloc(buffer as *mut c_void, required + 1) as *mut c_char;
+            buffer_len = required + 1;
+
+            if buffer.is_null() {
+                return (ptr::null_mut(), RUST_V0_DEMANGLE_NO_MEMORY);
+            }
+
+            let len = demangle_into(mangled, output_slice(buffer, buffer_len));
+            debug_assert_eq!(Ok(required), len);
+            required
+        }
+        Err(DemangleError::TooLong) => return (ptr::null_mut(), RUST_V0_DEMANGLE_NO_MEMORY),
+        Err(_) => return (ptr::null_mut(), RUST_V0_DEMANGLE_INVALID_NAME),
+    };
+
+    *buffer.add(len) = 0;
+
+    if !length.is_null() {
+        *length = buffer_len;
+    }
+
+    (buffer, RUST_V0_DEMANGLE_SUCCESS)
+}
+
+/// The part of a buffer of `len` bytes that the output can go into, leaving
+/// room for the terminating NUL.
+unsafe fn output_slice<'a>(buffer: *mut c_char, len: usize) -> &'a mut [u8] {
+    if buffer.is_null() || len == 0 {
+        &mut []
+    } else {
+        slice::from_raw_parts_mut(buffer as *mut u8, len - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Call the functions through their C declarations rather than as Rust
+    // functions.
+    mod c {
+        use std::ffi::{c_char, c_int, c_void};
+
+        extern "C" {
+            pub fn rust_v0_demangle(mangled: *const c_char,
+                                    out: *mut c_char,
+                                    out_len: usize,
+                                    flags: c_int)
+                                    -> c_int;
+            pub fn rust_v0_demangle_alloc(mangled: *const c_char,
+                                          output_buffer: *mut c_char,
+                                          length: *mut usize,
+                                          status: *mut c_int)
+                                          -> *mut c_char;
+            pub fn malloc(size: usize) -> *mut c_void;
+            pub fn free(ptr: *mut c_void);
+        }
+
+        // tests/demangle.c, compiled against include/std_mangle.h
+        #[link(name = "std_mangle_c_tests", kind = "static")]
+        extern "C" {
+            pub fn std_mangle_c_tests() -> c_int;
+        }
+    }
+
+    const MANGLED: &[u8] = b"_RINxC3std3FooKj7b_E\0";
+
+    fn c_str(s: &[u8]) -> *const c_char {
+        s.as_ptr() as *const c_char
+    }
+
+    unsafe fn to_str<'a>(s: *const c_char) -> &'a str {
+        CStr::from_ptr(s).to_str().unwrap()
+    }
+
+    #[test]
+    fn demangle_into_buffer() {
+        let mut out = [0x7f as c_char; 32];
+
+        unsafe {
+            assert_eq!(RUST_V0_DEMANGLE_SUCCESS,
+                       c::rust_v0_demangle(c_str(MANGLED), out.as_mut_ptr(), out.len(), 0));
+            assert_eq!("std::Foo<123>", to_str(out.as_ptr()));
+        }
+    }
+
+    #[test]
+    fn demangle_into_short_buffer() {
+        let mut out = [0x7f as c_char; 11];
+
+        unsafe {
+            assert_eq!(RUST_V0_DEMANGLE_NO_MEMORY,
+                       c::rust_v0_demangle(c_str(MANGLED), out.as_mut_ptr(), out.len(), 0));
+            assert_eq!("std::Foo<1", to_str(out.as_ptr()));
+        }
+    }
+
+    #[test]
+    fn demangle_invalid_arguments() {
+        let mut out = [0; 32];
+
+        unsafe {
+            assert_eq!(RUST_V0_DEMANGLE_INVALID_NAME,
+                       c::rust_v0_demangle(c_str(b"_ZN3fooE\0"), out.as_mut_ptr(), out.len(), 0));
+            assert_eq!(RUST_V0_DEMANGLE_INVALID_ARGUMENT,
+                       c::rust_v0_demangle(c_str(MANGLED), out.as_mut_ptr(), out.len(), 1));
+            assert_eq!(RUST_V0_DEMANGLE_INVALID_ARGUMENT,
+                       c::rust_v0_demangle(ptr::null(), out.as_mut_ptr(), out.len(), 0));
+            assert_eq!(RUST_V0_DEMANGLE_INVALID_ARGUMENT,
+                       c::rust_v0_demangle(c_str(MANGLED), out.as_mut_ptr(), 0, 0));
+        }
+    }
+
+    #[test]
+    fn demangle_alloc_new_buffer() {
+        let mut length = 0;
+        let mut status = 1;
+
+        unsafe {
+            let out = c::rust_v0_demangle_alloc(c_str(MANGLED), ptr::null_mut(), &mut length, &mut status);
+
+            assert_eq!(RUST_V0_DEMANGLE_SUCCESS, status);
+            assert_eq!(14, length);
+            assert_eq!("std::Foo<123>", to_str(out));
+
+            c::free(out as *mut c_void);
+        }
+    }
+
+    #[test]
+    fn demangle_alloc_grows_buffer() {
+        unsafe {
+            let buffer = c::malloc(4) as *mut c_char;
+            let mut length = 4;
+            let mut status = 1;
+
+            let out = c::rust_v0_demangle_alloc(c_str(MANGLED), buffer, &mut length, &mut status);
+
+            assert_eq!(RUST_V0_DEMANGLE_SUCCESS, status);
+            assert_eq!(14, length);
+            assert_eq!("std::Foo<123>", to_str(out));
+
+            // A large enough buffer is reused as is
+            let mut length = 64;
+            let buffer = c::malloc(length) as *mut c_char;
+            let reused = c::rust_v0_demangle_alloc(c_str(MANGLED), buffer, &mut length, &mut status);
+
+            assert_eq!(buffer, reused);
+            assert_eq!(64, length);
+            assert_eq!("std::Foo<123>", to_str(reused));
+
+            c::free(out as *mut c_void);
+            c::free(reused as *mut c_void);
+        }
+    }
+
+    #[test]
+    fn exponential_output_is_rejected() {
+        // Every tuple refers back to the previous one twice
+        let mut mangled = std_mangle_rs::generate::nested_backrefs(64).into_bytes();
+        mangled.push(0);
+
+        let mut out = [0 as c_char; 32];
+        let mut status = 1;
+
+        unsafe {
+            assert_eq!(RUST_V0_DEMANGLE_NO_MEMORY,
+                       c::rust_v0_demangle(c_str(&mangled), out.as_mut_ptr(), out.len(), 0));
+            assert_eq!("std::Foo<std::Bar,(&std::Bar,&s", to_str(out.as_ptr()));
+
+            let out = c::rust_v0_demangle_alloc(c_str(&mangled), ptr::null_mut(), ptr::null_mut(), &mut status);
+            assert!(out.is_null());
+            assert_eq!(RUST_V0_DEMANGLE_NO_MEMORY, status);
+        }
+    }
+
+    #[test]
+    fn demangle_alloc_failures() {
+        let mut status = 1;
+
+        unsafe {
+            let out = c::rust_v0_demangle_alloc(c_str(b"_RNvC3foo\0"), ptr::null_mut(), ptr::null_mut(), &mut status);
+            assert!(out.is_null());
+            assert_eq!(RUST_V0_DEMANGLE_INVALID_NAME, status);
+
+            // A buffer without a length is rejected before it is touched
+            let mut buffer = [0 as c_char; 4];
+            let out = c::rust_v0_demangle_alloc(c_str(MANGLED), buffer.as_mut_ptr(), ptr::null_mut(), &mut status);
+            assert!(out.is_null());
+            assert_eq!(RUST_V0_DEMANGLE_INVALID_ARGUMENT, status);
+        }
+    }
+
+    #[test]
+    fn c_api_through_the_header() {
+        // A non-zero result is the line of the failed check
+        assert_eq!(0, unsafe { c::std_mangle_c_tests() });
+    }
+}
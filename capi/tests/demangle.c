@@ -0,0 +1,52 @@
+/*
+ * Calls the C API through the checked-in header. Built by build.rs and run
+ * by the `c_api_through_the_header` test.
+ */
+
+#include <stdlib.h>
+#include <string.h>
+
+#include "std_mangle.h"
+
+#define CHECK(condition) \
+    do { if (!(condition)) return __LINE__; } while (0)
+
+/* Returns 0 on success and the line of the failed check otherwise. */
+int std_mangle_c_tests(void) {
+    char out[32];
+    char *alloced;
+    size_t length = 0;
+    int status = 1;
+
+    CHECK(rust_v0_demangle("_RINxC3std3FooKj7b_E", out, sizeof(out), 0)
+          == RUST_V0_DEMANGLE_SUCCESS);
+    CHECK(strcmp(out, "std::Foo<123>") == 0);
+
+    CHECK(rust_v0_demangle("_RINxC3std3FooKj7b_E", out, 5, 0)
+          == RUST_V0_DEMANGLE_NO_MEMORY);
+    CHECK(strcmp(out, "std:") == 0);
+
+    CHECK(rust_v0_demangle("_RNvC3foo", out, sizeof(out), 0)
+          == RUST_V0_DEMANGLE_INVALID_NAME);
+    CHECK(rust_v0_demangle(NULL, out, sizeof(out), 0)
+          == RUST_V0_DEMANGLE_INVALID_ARGUMENT);
+    CHECK(rust_v0_demangle("_RINxC3std3FooKj7b_E", out, sizeof(out), 1)
+          == RUST_V0_DEMANGLE_INVALID_ARGUMENT);
+
+    /* A base-62 number that overflows when one is added to it */
+    CHECK(rust_v0_demangle("_RNvCslYGhA16ahyf_3foo3bar", out, sizeof(out), 0)
+          == RUST_V0_DEMANGLE_INVALID_NAME);
+
+    alloced = rust_v0_demangle_alloc("_RINxC3std3FooKj7b_E", NULL, &length, &status);
+    CHECK(alloced != NULL);
+    CHECK(status == RUST_V0_DEMANGLE_SUCCESS);
+    CHECK(strcmp(alloced, "std::Foo<123>") == 0);
+    CHECK(length >= strlen(alloced) + 1);
+    free(alloced);
+
+    alloced = rust_v0_demangle_alloc("_RNvCslYGhA16ahyf_3foo3bar", NULL, NULL, &status);
+    CHECK(alloced == NULL);
+    CHECK(status == RUST_V0_DEMANGLE_INVALID_NAME);
+
+    return 0;
+}
@@ -0,0 +1,145 @@
+//! `quickcheck::Arbitrary` implementations for the AST, using the symbol
+//! generator from `generate`.
+
+use ast::*;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use generate::{Generator, RandomSource};
+use quickcheck::{Arbitrary, Gen};
+
+/// The maximum nesting depth of generated paths and types.
+const MAX_DEPTH: usize = 4;
+
+struct GenSource<'g, G: 'g>(&'g mut G);
+
+impl<'g, G: Gen> RandomSource for GenSource<'g, G> {
+    fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+}
+
+/// A generator whose depth is bounded by the size of `g`.
+fn generator<G: Gen>(g: &mut G) -> Generator<GenSource<'_, G>> {
+    let max_depth = g.size().min(MAX_DEPTH);
+    Generator::new(GenSource(g)).with_max_depth(max_depth)
+}
+
+impl Arbitrary for Symbol<'static> {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        generator(g).symbol()
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let mut smaller = Vec::new();
+
+        if self.instantiating_crate.is_some() {
+            smaller.push(Symbol { instantiating_crate: None, ..self.clone() });
+        }
+
+        for path in self.path.shrink() {
+            smaller.push(Symbol { path, ..self.clone() });
+        }
+
+        Box::new(smaller.into_iter())
+    }
+}
+
+impl Arbitrary for Path<'static> {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        let mut generator = generator(g);
+        let depth = generator.depth();
+        generator.gen_path(depth)
+    }
+
+    /// Shrinks to the paths this path is built on.
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let inner = match *self {
+            Path::CrateRoot { .. } |
+            Path::Invalid => None,
+            Path::InherentImpl { ref impl_path, .. } |
+            Path::TraitImpl { ref impl_path, .. } => Some(&impl_path.path),
+            Path::TraitDef { ref trait_name, .. } => Some(trait_name),
+            Path::Nested { ref inner, .. } |
+            Path::Generic { ref inner, .. } => Some(inner),
+        };
+
+        Box::new(inner.map(|path| (**path).clone()).into_iter())
+    }
+}
+
+impl Arbitrary for Type<'static> {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        let mut generator = generator(g);
+        let depth = generator.depth();
+        generator.gen_type(depth)
+    }
+}
+
+impl Arbitrary for Const<'static> {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        generator(g).gen_const()
+    }
+}
+
+impl Arbitrary for FnSig<'static> {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        let mut generator = generator(g);
+        let depth = generator.depth();
+        generator.gen_fn_sig(depth)
+    }
+}
+
+impl Arbitrary for DynBounds<'static> {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        let mut generator = generator(g);
+        let depth = generator.depth();
+        generator.gen_dyn_bounds(depth)
+    }
+}
+
+impl Arbitrary for Ident<'static> {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        generator(g).gen_ident()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::borrow::Cow;
+    use alloc::string::String;
+    use alloc::sync::Arc;
+    use ast_demangle::AstDemangle;
+    use ast_mangle::mangle;
+    use parse::parse;
+    use stream_demangle::demangle;
+
+    quickcheck! {
+        fn parse_inverts_mangle(symbol: Symbol<'static>) -> bool {
+            let mangled = mangle(&symbol).unwrap();
+            parse(mangled.as_bytes()) == Ok(symbol)
+        }
+
+        fn demangling_generated_symbols_never_fails(symbol: Symbol<'static>) -> bool {
+            let mangled = mangle(&symbol).unwrap();
+
+            let mut streamed = String::new();
+            demangle(mangled.as_bytes(), &mut streamed).is_ok() && streamed == symbol.demangle()
+        }
+
+        fn types_round_trip(ty: Type<'static>) -> bool {
+            let symbol = Symbol {
+                version: None,
+                path: Path::Generic {
+                    inner: Arc::new(Path::CrateRoot {
+                        id: Ident { dis: Base62Number(0), u_ident: UIdent(Cow::Borrowed("std")) },
+                    }),
+                    args: vec![GenericArg::Type(ty)],
+                },
+                instantiating_crate: None,
+            };
+
+            parse(mangle(&symbol).unwrap().as_bytes()) == Ok(symbol)
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use std::sync::Arc;
+use alloc::borrow::Cow;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
 pub struct Base62Number(pub u64);
@@ -7,39 +9,44 @@ pub struct Base62Number(pub u64);
 pub struct DecimalNumber(pub u64);
 
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
-pub struct Ident {
+pub struct Ident<'input> {
     pub dis: Base62Number,
-    pub u_ident: UIdent,
+    pub u_ident: UIdent<'input>,
 }
 
+/// An identifier. Borrows from the mangled input unless it had to be decoded
+/// from punycode.
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
-pub struct UIdent(pub String);
+pub struct UIdent<'input>(pub Cow<'input, str>);
 
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
 pub struct Namespace(pub u8);
 
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
-pub enum Path {
-    CrateRoot { id: Ident },
-    InherentImpl { impl_path: ImplPath, self_type: Type },
-    TraitImpl { impl_path: ImplPath, self_type: Type, trait_name: Arc<Path> },
-    TraitDef { self_type: Type, trait_name: Arc<Path> },
-    Nested { ns: Namespace, inner: Arc<Path>, ident: Ident },
-    Generic { inner: Arc<Path>, args: Vec<GenericArg> },
+pub enum Path<'input> {
+    CrateRoot { id: Ident<'input> },
+    InherentImpl { impl_path: ImplPath<'input>, self_type: Type<'input> },
+    TraitImpl { impl_path: ImplPath<'input>, self_type: Type<'input>, trait_name: Arc<Path<'input>> },
+    TraitDef { self_type: Type<'input>, trait_name: Arc<Path<'input>> },
+    /// `ident` is `None` if it could not be parsed; see `parse::parse_lenient`.
+    Nested { ns: Namespace, inner: Arc<Path<'input>>, ident: Option<Ident<'input>> },
+    Generic { inner: Arc<Path<'input>>, args: Vec<GenericArg<'input>> },
+    /// A path that could not be parsed; see `parse::parse_lenient`.
+    Invalid,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
-pub struct ImplPath {
+pub struct ImplPath<'input> {
     // Should this be optional?
     pub dis: Option<Base62Number>,
-    pub path: Arc<Path>,
+    pub path: Arc<Path<'input>>,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
-pub enum GenericArg {
+pub enum GenericArg<'input> {
     Lifetime(Lifetime),
-    Type(Type),
-    Const(Const),
+    Type(Type<'input>),
+    Const(Const<'input>),
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
@@ -53,57 +60,61 @@ pub struct Binder {
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
-pub enum Type {
+pub enum Type<'input> {
     BasicType(BasicType),
-    Array(Arc<Type>, Arc<Const>),
-    Slice(Arc<Type>),
-    Named(Arc<Path>),
-    Tuple(Vec<Type>),
-    Ref(Option<Lifetime>, Arc<Type>),
-    RefMut(Option<Lifetime>, Arc<Type>),
-    RawPtrConst(Arc<Type>),
-    RawPtrMut(Arc<Type>),
-    Fn(Arc<FnSig>),
-    DynTrait(Arc<DynBounds>, Lifetime),
+    Array(Arc<Type<'input>>, Arc<Const<'input>>),
+    Slice(Arc<Type<'input>>),
+    Named(Arc<Path<'input>>),
+    Tuple(Vec<Type<'input>>),
+    Ref(Option<Lifetime>, Arc<Type<'input>>),
+    RefMut(Option<Lifetime>, Arc<Type<'input>>),
+    RawPtrConst(Arc<Type<'input>>),
+    RawPtrMut(Arc<Type<'input>>),
+    Fn(Arc<FnSig<'input>>),
+    DynTrait(Arc<DynBounds<'input>>, Lifetime),
+    /// A type that could not be parsed; see `parse::parse_lenient`.
+    Invalid,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
-pub struct FnSig {
+pub struct FnSig<'input> {
     pub binder: Binder,
     pub is_unsafe: bool,
-    pub abi: Option<Abi>,
-    pub param_types: Vec<Type>,
-    pub return_type: Type,
+    pub abi: Option<Abi<'input>>,
+    pub param_types: Vec<Type<'input>>,
+    pub return_type: Type<'input>,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
-pub enum Abi {
+pub enum Abi<'input> {
     C,
-    Named(UIdent),
+    Named(UIdent<'input>),
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
-pub struct DynBounds {
+pub struct DynBounds<'input> {
     pub binder: Binder,
-    pub traits: Vec<DynTrait>,
+    pub traits: Vec<DynTrait<'input>>,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
-pub struct DynTrait {
-    pub path: Path,
-    pub assoc_type_bindings: Vec<DynTraitAssocBinding>,
+pub struct DynTrait<'input> {
+    pub path: Path<'input>,
+    pub assoc_type_bindings: Vec<DynTraitAssocBinding<'input>>,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
-pub struct DynTraitAssocBinding {
-    pub ident: UIdent,
-    pub ty: Type,
+pub struct DynTraitAssocBinding<'input> {
+    pub ident: UIdent<'input>,
+    pub ty: Type<'input>,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
-pub enum Const {
-    Value(Type, u64),
-    Placeholder(Type),
+pub enum Const<'input> {
+    Value(Type<'input>, u64),
+    Placeholder(Type<'input>),
+    /// A const that could not be parsed; see `parse::parse_lenient`.
+    Invalid,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
@@ -132,8 +143,8 @@ pub enum BasicType {
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
-pub struct Symbol {
+pub struct Symbol<'input> {
     pub version: Option<DecimalNumber>,
-    pub path: Path,
-    pub instantiating_crate: Option<Path>,
+    pub path: Path<'input>,
+    pub instantiating_crate: Option<Path<'input>>,
 }
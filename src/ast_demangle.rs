@@ -1,5 +1,6 @@
 use ast::*;
-use std::fmt::Write;
+use alloc::string::String;
+use core::fmt::Write;
 
 pub trait AstDemangle {
     fn demangle_to_string(&self, out: &mut String);
@@ -11,7 +12,7 @@ pub trait AstDemangle {
     }
 }
 
-impl AstDemangle for Symbol {
+impl<'input> AstDemangle for Symbol<'input> {
     fn demangle_to_string(&self, out: &mut String) {
         self.path.demangle_to_string(out);
 
@@ -22,7 +23,7 @@ impl AstDemangle for Symbol {
     }
 }
 
-impl AstDemangle for Ident {
+impl<'input> AstDemangle for Ident<'input> {
     fn demangle_to_string(&self, out: &mut String) {
 
         self.u_ident.demangle_to_string(out);
@@ -32,13 +33,13 @@ impl AstDemangle for Ident {
     }
 }
 
-impl AstDemangle for UIdent {
+impl<'input> AstDemangle for UIdent<'input> {
     fn demangle_to_string(&self, out: &mut String) {
         out.push_str(&self.0[..]);
     }
 }
 
-impl AstDemangle for Path {
+impl<'input> AstDemangle for Path<'input> {
     fn demangle_to_string(&self, out: &mut String) {
         match *self {
             Path::CrateRoot { ref id } => {
@@ -60,40 +61,50 @@ impl AstDemangle for Path {
             Path::Nested { ref ns, ref inner, ref ident } => {
                 inner.demangle_to_string(out);
 
-                if *ns == Namespace(b'C') {
-                    write!(out, "::{{closure}}[{}]", ident.dis.0).unwrap();
-                } else if ident.u_ident.0.len() > 0 {
-                    out.push_str("::");
-                    ident.demangle_to_string(out);
+                match *ident {
+                    None => out.push_str("::{invalid}"),
+                    Some(ref ident) if *ns == Namespace(b'C') => {
+                        write!(out, "::{{closure}}[{}]", ident.dis.0).unwrap();
+                    }
+                    Some(ref ident) => {
+                        if !ident.u_ident.0.is_empty() {
+                            out.push_str("::");
+                            ident.demangle_to_string(out);
+                        }
+                    }
                 }
             }
             Path::Generic { ref inner, ref args } => {
                 inner.demangle_to_string(out);
                 out.push('<');
-                for arg in args {
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
                     arg.demangle_to_string(out);
-                    out.push(',');
                 }
-                out.pop();
                 out.push('>');
             }
+            Path::Invalid => {
+                out.push_str("{invalid}");
+            }
         }
 
     }
 }
 
-impl AstDemangle for DynBounds {
+impl<'input> AstDemangle for DynBounds<'input> {
     fn demangle_to_string(&self, out: &mut String) {
-        for tr in self.traits.iter() {
+        for (i, tr) in self.traits.iter().enumerate() {
+            if i > 0 {
+                out.push('+');
+            }
             tr.demangle_to_string(out);
-            out.push_str("+");
         }
-
-        out.pop();
     }
 }
 
-impl AstDemangle for GenericArg {
+impl<'input> AstDemangle for GenericArg<'input> {
     fn demangle_to_string(&self, out: &mut String) {
         match *self {
             GenericArg::Lifetime(ref lt) => {
@@ -115,38 +126,39 @@ impl AstDemangle for Lifetime {
     }
 }
 
-impl AstDemangle for Type {
+impl<'input> AstDemangle for Type<'input> {
     fn demangle_to_string(&self, out: &mut String) {
         match *self {
             Type::BasicType(bt) => {
                 bt.demangle_to_string(out);
             }
             Type::Array(ref inner, ref len) => {
-                out.push_str("[");
+                out.push('[');
                 inner.demangle_to_string(out);
                 out.push_str("; ");
                 len.demangle_to_string(out);
-                out.push_str("]");
+                out.push(']');
             }
             Type::Slice(ref inner) => {
-                out.push_str("[");
+                out.push('[');
                 inner.demangle_to_string(out);
-                out.push_str("]");
+                out.push(']');
             }
             Type::Named(ref path) => {
                 path.demangle_to_string(out);
             }
             Type::Tuple(ref inner) => {
-                out.push_str("(");
-                for ty in inner {
+                out.push('(');
+                for (i, ty) in inner.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
                     ty.demangle_to_string(out);
-                    out.push_str(",");
                 }
-                out.pop();
-                out.push_str(")");
+                out.push(')');
             }
             Type::Ref(_, ref ty) => {
-                out.push_str("&");
+                out.push('&');
                 ty.demangle_to_string(out);
             }
             Type::RefMut(_, ref ty) => {
@@ -167,12 +179,15 @@ impl AstDemangle for Type {
             Type::DynTrait(ref bounds, _) => {
                 bounds.demangle_to_string(out);
             }
+            Type::Invalid => {
+                out.push_str("{invalid}");
+            }
 
         }
     }
 }
 
-impl AstDemangle for FnSig {
+impl<'input> AstDemangle for FnSig<'input> {
     fn demangle_to_string(&self, out: &mut String) {
         if self.is_unsafe {
             out.push_str("unsafe ");
@@ -181,20 +196,19 @@ impl AstDemangle for FnSig {
         if let Some(ref abi) = self.abi {
             out.push_str("extern ");
             abi.demangle_to_string(out);
-            out.push_str(" ");
+            out.push(' ');
         }
 
         out.push_str("fn(");
 
-        if self.param_types.len() > 0 {
-            for param_type in self.param_types.iter() {
-                param_type.demangle_to_string(out);
-                out.push_str(",");
+        for (i, param_type) in self.param_types.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
             }
-            out.pop();
+            param_type.demangle_to_string(out);
         }
 
-        out.push_str(")");
+        out.push(')');
 
         if self.return_type != Type::BasicType(BasicType::Unit) {
             out.push_str(" -> ");
@@ -203,7 +217,7 @@ impl AstDemangle for FnSig {
     }
 }
 
-impl AstDemangle for Abi {
+impl<'input> AstDemangle for Abi<'input> {
     fn demangle_to_string(&self, out: &mut String) {
         out.push('"');
         match *self {
@@ -219,26 +233,27 @@ impl AstDemangle for Abi {
 }
 
 
-impl AstDemangle for DynTrait {
+impl<'input> AstDemangle for DynTrait<'input> {
     fn demangle_to_string(&self, out: &mut String) {
         self.path.demangle_to_string(out);
 
-        if self.assoc_type_bindings.len() > 0 {
+        if !self.assoc_type_bindings.is_empty() {
             out.push('<');
 
-            for binding in self.assoc_type_bindings.iter() {
+            for (i, binding) in self.assoc_type_bindings.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
                 binding.demangle_to_string(out);
-                out.push_str(", ");
             }
 
-            out.pop();
             out.push('>');
         }
 
     }
 }
 
-impl AstDemangle for DynTraitAssocBinding {
+impl<'input> AstDemangle for DynTraitAssocBinding<'input> {
     fn demangle_to_string(&self, out: &mut String) {
         self.ident.demangle_to_string(out);
         out.push('=');
@@ -246,7 +261,7 @@ impl AstDemangle for DynTraitAssocBinding {
     }
 }
 
-impl AstDemangle for Const {
+impl<'input> AstDemangle for Const<'input> {
     fn demangle_to_string(&self, out: &mut String) {
         match *self {
             Const::Value(Type::BasicType(BasicType::I8), i) |
@@ -269,6 +284,9 @@ impl AstDemangle for Const {
                 ty.demangle_to_string(out);
                 out.push('}');
             }
+            Const::Invalid => {
+                out.push_str("{invalid}");
+            }
         }
     }
 }
@@ -0,0 +1,639 @@
+use ast::*;
+use builtin_punycode;
+use int_radix::radix;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::hash::{Hash, Hasher};
+use core::mem;
+
+/// Generates the mangled name for a symbol's AST, compressing repeated paths,
+/// types and constants into back-references the way rustc does. Parsing the
+/// result yields the same AST.
+pub fn mangle(symbol: &Symbol) -> Result<String, MangleError> {
+    let mut mangler = Mangler {
+        out: String::from("_R"),
+        paths: BTreeMap::new(),
+        types: BTreeMap::new(),
+        consts: BTreeMap::new(),
+        hashes: NodeHashes::default(),
+    };
+
+    mangler.mangle_symbol(symbol)?;
+    Ok(mangler.out)
+}
+
+/// Why an AST has no mangled form.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MangleError {
+    /// The AST contains an `Invalid` node or a missing identifier, as a
+    /// leniently parsed symbol can.
+    Invalid,
+    /// An impl path has a disambiguator of zero. Disambiguators are encoded
+    /// as one less than their value, so they must be non-zero.
+    ZeroDisambiguator,
+    /// An identifier starts with a digit, which would be read as part of
+    /// its length.
+    LeadingDigit,
+    /// A non-ASCII identifier is too long to be encoded with punycode.
+    IdentTooLong,
+}
+
+impl fmt::Display for MangleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MangleError::Invalid => write!(f, "cannot mangle an invalid node"),
+            MangleError::ZeroDisambiguator => write!(f, "cannot mangle a zero disambiguator"),
+            MangleError::LeadingDigit => {
+                write!(f, "cannot mangle an identifier that starts with a digit")
+            }
+            MangleError::IdentTooLong => write!(f, "identifier too long for punycode"),
+        }
+    }
+}
+
+/// The nodes that have been emitted, by their hash, with the positions they
+/// were emitted at.
+type Emitted<'a, T> = BTreeMap<u64, Vec<(&'a T, usize)>>;
+
+struct Mangler<'a, 'input: 'a> {
+    out: String,
+    // The positions at which paths, types and constants have been emitted,
+    // for back-references to them.
+    paths: Emitted<'a, Path<'input>>,
+    types: Emitted<'a, Type<'input>>,
+    consts: Emitted<'a, Const<'input>>,
+    hashes: NodeHashes,
+}
+
+impl<'a, 'input> Mangler<'a, 'input> {
+
+    fn mangle_symbol(&mut self, symbol: &'a Symbol<'input>) -> Result<(), MangleError> {
+        if let Some(DecimalNumber(version)) = symbol.version {
+            write!(self.out, "{}", version).unwrap();
+        }
+
+        self.mangle_path(&symbol.path)?;
+
+        if let Some(ref instantiating_crate) = symbol.instantiating_crate {
+            self.mangle_path(instantiating_crate)?;
+        }
+
+        Ok(())
+    }
+
+    fn mangle_path(&mut self, path: &'a Path<'input>) -> Result<(), MangleError> {
+        let hash = self.hashes.path(path);
+        if let Some(pos) = find(&self.paths, hash, path) {
+            self.mangle_backref(pos);
+            return Ok(());
+        }
+
+        let start = self.out.len();
+
+        match *path {
+            Path::CrateRoot { ref id } => {
+                self.out.push('C');
+                self.mangle_ident(id)?;
+            }
+            Path::InherentImpl { ref impl_path, ref self_type } => {
+                self.out.push('M');
+                self.mangle_impl_path(impl_path)?;
+                self.mangle_type(self_type)?;
+            }
+            Path::TraitImpl { ref impl_path, ref self_type, ref trait_name } => {
+                self.out.push('X');
+                self.mangle_impl_path(impl_path)?;
+                self.mangle_type(self_type)?;
+                self.mangle_path(trait_name)?;
+            }
+            Path::TraitDef { ref self_type, ref trait_name } => {
+                self.out.push('Y');
+                self.mangle_type(self_type)?;
+                self.mangle_path(trait_name)?;
+            }
+            Path::Nested { ref ns, ref inner, ref ident } => {
+                self.out.push('N');
+                self.out.push(ns.0 as char);
+                self.mangle_path(inner)?;
+
+                match *ident {
+                    Some(ref ident) => self.mangle_ident(ident)?,
+                    None => return Err(MangleError::Invalid),
+                }
+            }
+            Path::Generic { ref inner, ref args } => {
+                self.out.push('I');
+                self.mangle_path(inner)?;
+
+                for arg in args {
+                    match *arg {
+                        GenericArg::Lifetime(ref lt) => self.mangle_lifetime(lt),
+                        GenericArg::Type(ref ty) => self.mangle_type(ty)?,
+                        GenericArg::Const(ref k) => {
+                            self.out.push('K');
+                            self.mangle_const(k)?;
+                        }
+                    }
+                }
+
+                self.out.push('E');
+            }
+            Path::Invalid => return Err(MangleError::Invalid),
+        }
+
+        self.paths.entry(hash).or_default().push((path, start));
+        Ok(())
+    }
+
+    fn mangle_impl_path(&mut self, impl_path: &'a ImplPath<'input>) -> Result<(), MangleError> {
+        if let Some(dis) = impl_path.dis {
+            self.mangle_disambiguator(dis)?;
+        }
+
+        self.mangle_path(&impl_path.path)
+    }
+
+    fn mangle_type(&mut self, ty: &'a Type<'input>) -> Result<(), MangleError> {
+        // Basic types are shorter than any back-reference
+        if let Type::BasicType(basic_type) = *ty {
+            self.out.push(basic_type_tag(basic_type));
+            return Ok(());
+        }
+
+        let hash = self.hashes.ty(ty);
+        if let Some(pos) = find(&self.types, hash, ty) {
+            self.mangle_backref(pos);
+            return Ok(());
+        }
+
+        self.mangle_type_uncached(ty, hash)
+    }
+
+    fn mangle_type_uncached(&mut self, ty: &'a Type<'input>, hash: u64) -> Result<(), MangleError> {
+        let start = self.out.len();
+
+        match *ty {
+            Type::BasicType(basic_type) => {
+                self.out.push(basic_type_tag(basic_type));
+                return Ok(());
+            }
+            Type::Array(ref inner, ref len) => {
+                self.out.push('A');
+                self.mangle_type(inner)?;
+                self.mangle_const(len)?;
+            }
+            Type::Slice(ref inner) => {
+                self.out.push('S');
+                self.mangle_type(inner)?;
+            }
+            Type::Named(ref path) => {
+                self.mangle_path(path)?;
+            }
+            Type::Tuple(ref components) => {
+                self.out.push('T');
+                for component in components {
+                    self.mangle_type(component)?;
+                }
+                self.out.push('E');
+            }
+            Type::Ref(ref lifetime, ref inner) |
+            Type::RefMut(ref lifetime, ref inner) => {
+                self.out.push(if let Type::Ref(..) = *ty { 'R' } else { 'Q' });
+
+                if let Some(ref lifetime) = *lifetime {
+                    self.mangle_lifetime(lifetime);
+                }
+
+                self.mangle_type(inner)?;
+            }
+            Type::RawPtrConst(ref inner) => {
+                self.out.push('P');
+                self.mangle_type(inner)?;
+            }
+            Type::RawPtrMut(ref inner) => {
+                self.out.push('O');
+                self.mangle_type(inner)?;
+            }
+            Type::Fn(ref fn_sig) => {
+                self.out.push('F');
+                self.mangle_fn_sig(fn_sig)?;
+            }
+            Type::DynTrait(ref bounds, ref lifetime) => {
+                self.out.push('D');
+                self.mangle_dyn_bounds(bounds)?;
+                self.mangle_lifetime(lifetime);
+            }
+            Type::Invalid => return Err(MangleError::Invalid),
+        }
+
+        self.types.entry(hash).or_default().push((ty, start));
+        Ok(())
+    }
+
+    fn mangle_fn_sig(&mut self, fn_sig: &'a FnSig<'input>) -> Result<(), MangleError> {
+        self.mangle_binder(&fn_sig.binder);
+
+        if fn_sig.is_unsafe {
+            self.out.push('U');
+        }
+
+        if let Some(ref abi) = fn_sig.abi {
+            self.out.push('K');
+
+            match *abi {
+                Abi::C => self.out.push('C'),
+                Abi::Named(ref name) => self.mangle_uident(name)?,
+            }
+        }
+
+        for param_type in &fn_sig.param_types {
+            self.mangle_type(param_type)?;
+        }
+
+        self.out.push('E');
+        self.mangle_type(&fn_sig.return_type)
+    }
+
+    fn mangle_dyn_bounds(&mut self, bounds: &'a DynBounds<'input>) -> Result<(), MangleError> {
+        self.mangle_binder(&bounds.binder);
+
+        for tr in &bounds.traits {
+            self.mangle_path(&tr.path)?;
+
+            for binding in &tr.assoc_type_bindings {
+                self.out.push('p');
+                self.mangle_uident(&binding.ident)?;
+                self.mangle_type(&binding.ty)?;
+            }
+        }
+
+        self.out.push('E');
+        Ok(())
+    }
+
+    fn mangle_const(&mut self, k: &'a Const<'input>) -> Result<(), MangleError> {
+        let hash = self.hashes.konst(k);
+        if let Some(pos) = find(&self.consts, hash, k) {
+            self.mangle_backref(pos);
+            return Ok(());
+        }
+
+        let start = self.out.len();
+
+        // A back-reference at the start of a constant always refers to a
+        // whole constant, so the type cannot be replaced by one.
+        match *k {
+            Const::Value(ref ty, value) => {
+                let ty_hash = self.hashes.ty(ty);
+                self.mangle_type_uncached(ty, ty_hash)?;
+                write!(self.out, "{}_", radix(16, value)).unwrap();
+            }
+            Const::Placeholder(ref ty) => {
+                let ty_hash = self.hashes.ty(ty);
+                self.mangle_type_uncached(ty, ty_hash)?;
+                self.out.push('p');
+            }
+            Const::Invalid => return Err(MangleError::Invalid),
+        }
+
+        self.consts.entry(hash).or_default().push((k, start));
+        Ok(())
+    }
+
+    fn mangle_ident(&mut self, ident: &Ident) -> Result<(), MangleError> {
+        if ident.dis.0 != 0 {
+            self.mangle_disambiguator(ident.dis)?;
+        }
+
+        self.mangle_uident(&ident.u_ident)
+    }
+
+    fn mangle_uident(&mut self, ident: &UIdent) -> Result<(), MangleError> {
+        if ident.0.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(MangleError::LeadingDigit);
+        }
+
+        if ident.0.is_ascii() {
+            write!(self.out, "{}{}", ident.0.len(), ident.0).unwrap();
+        } else {
+            let encoded = builtin_punycode::encode_v0_ident(&ident.0)
+                .ok_or(MangleError::IdentTooLong)?;
+            write!(self.out, "u{}{}", encoded.len(), encoded).unwrap();
+        }
+
+        Ok(())
+    }
+
+    /// Disambiguators are encoded as one less than their value.
+    fn mangle_disambiguator(&mut self, dis: Base62Number) -> Result<(), MangleError> {
+        if dis.0 == 0 {
+            return Err(MangleError::ZeroDisambiguator);
+        }
+
+        self.out.push('s');
+        self.mangle_base62_number(dis.0 - 1);
+        Ok(())
+    }
+
+    fn mangle_lifetime(&mut self, lifetime: &Lifetime) {
+        self.out.push('L');
+        self.mangle_base62_number(lifetime.debruijn_index.0);
+    }
+
+    fn mangle_binder(&mut self, binder: &Binder) {
+        self.out.push('G');
+        self.mangle_base62_number(binder.count.0);
+    }
+
+    fn mangle_backref(&mut self, pos: usize) {
+        self.out.push('B');
+        // Account for the `_R` prefix
+        self.mangle_base62_number((pos - 2) as u64);
+    }
+
+    fn mangle_base62_number(&mut self, n: u64) {
+        if n != 0 {
+            write!(self.out, "{}", radix(62, n - 1)).unwrap();
+        }
+
+        self.out.push('_');
+    }
+}
+
+fn find<T: PartialEq>(emitted: &Emitted<T>, hash: u64, node: &T) -> Option<usize> {
+    emitted.get(&hash)?.iter().find(|&&(n, _)| n == node).map(|&(_, pos)| pos)
+}
+
+/// Structural hashes of the paths, types and constants of an AST, remembered
+/// by address. Equal nodes hash alike no matter whether they are shared, and
+/// a subtree that is shared through an `Arc` is only hashed once, so hashing
+/// all nodes takes linear time even where expanding the AST would not.
+#[derive(Default)]
+struct NodeHashes {
+    paths: BTreeMap<usize, u64>,
+    types: BTreeMap<usize, u64>,
+    consts: BTreeMap<usize, u64>,
+}
+
+impl NodeHashes {
+    fn path(&mut self, path: &Path) -> u64 {
+        let key = path as *const Path as usize;
+        if let Some(&hash) = self.paths.get(&key) {
+            return hash;
+        }
+
+        let mut h = Fnv::default();
+        mem::discriminant(path).hash(&mut h);
+
+        match *path {
+            Path::CrateRoot { ref id } => id.hash(&mut h),
+            Path::InherentImpl { ref impl_path, ref self_type } => {
+                impl_path.dis.hash(&mut h);
+                h.write_u64(self.path(&impl_path.path));
+                h.write_u64(self.ty(self_type));
+            }
+            Path::TraitImpl { ref impl_path, ref self_type, ref trait_name } => {
+                impl_path.dis.hash(&mut h);
+                h.write_u64(self.path(&impl_path.path));
+                h.write_u64(self.ty(self_type));
+                h.write_u64(self.path(trait_name));
+            }
+            Path::TraitDef { ref self_type, ref trait_name } => {
+                h.write_u64(self.ty(self_type));
+                h.write_u64(self.path(trait_name));
+            }
+            Path::Nested { ref ns, ref inner, ref ident } => {
+                ns.hash(&mut h);
+                h.write_u64(self.path(inner));
+                ident.hash(&mut h);
+            }
+            Path::Generic { ref inner, ref args } => {
+                h.write_u64(self.path(inner));
+                h.write_usize(args.len());
+
+                for arg in args {
+                    mem::discriminant(arg).hash(&mut h);
+
+                    match *arg {
+                        GenericArg::Lifetime(ref lt) => lt.hash(&mut h),
+                        GenericArg::Type(ref ty) => h.write_u64(self.ty(ty)),
+                        GenericArg::Const(ref k) => h.write_u64(self.konst(k)),
+                    }
+                }
+            }
+            Path::Invalid => {}
+        }
+
+        self.paths.insert(key, h.0);
+        h.0
+    }
+
+    fn ty(&mut self, ty: &Type) -> u64 {
+        let key = ty as *const Type as usize;
+        if let Some(&hash) = self.types.get(&key) {
+            return hash;
+        }
+
+        let mut h = Fnv::default();
+        mem::discriminant(ty).hash(&mut h);
+
+        match *ty {
+            Type::BasicType(basic_type) => basic_type.hash(&mut h),
+            Type::Array(ref inner, ref len) => {
+                h.write_u64(self.ty(inner));
+                h.write_u64(self.konst(len));
+            }
+            Type::Slice(ref inner) |
+            Type::RawPtrConst(ref inner) |
+            Type::RawPtrMut(ref inner) => h.write_u64(self.ty(inner)),
+            Type::Named(ref path) => h.write_u64(self.path(path)),
+            Type::Tuple(ref components) => {
+                h.write_usize(components.len());
+                for component in components {
+                    h.write_u64(self.ty(component));
+                }
+            }
+            Type::Ref(ref lifetime, ref inner) |
+            Type::RefMut(ref lifetime, ref inner) => {
+                lifetime.hash(&mut h);
+                h.write_u64(self.ty(inner));
+            }
+            Type::Fn(ref fn_sig) => {
+                fn_sig.binder.hash(&mut h);
+                fn_sig.is_unsafe.hash(&mut h);
+                fn_sig.abi.hash(&mut h);
+                h.write_usize(fn_sig.param_types.len());
+                for param_type in &fn_sig.param_types {
+                    h.write_u64(self.ty(param_type));
+                }
+                h.write_u64(self.ty(&fn_sig.return_type));
+            }
+            Type::DynTrait(ref bounds, ref lifetime) => {
+                bounds.binder.hash(&mut h);
+                h.write_usize(bounds.traits.len());
+                for tr in &bounds.traits {
+                    h.write_u64(self.path(&tr.path));
+                    h.write_usize(tr.assoc_type_bindings.len());
+                    for binding in &tr.assoc_type_bindings {
+                        binding.ident.hash(&mut h);
+                        h.write_u64(self.ty(&binding.ty));
+                    }
+                }
+                lifetime.hash(&mut h);
+            }
+            Type::Invalid => {}
+        }
+
+        self.types.insert(key, h.0);
+        h.0
+    }
+
+    fn konst(&mut self, k: &Const) -> u64 {
+        let key = k as *const Const as usize;
+        if let Some(&hash) = self.consts.get(&key) {
+            return hash;
+        }
+
+        let mut h = Fnv::default();
+        mem::discriminant(k).hash(&mut h);
+
+        match *k {
+            Const::Value(ref ty, value) => {
+                h.write_u64(self.ty(ty));
+                h.write_u64(value);
+            }
+            Const::Placeholder(ref ty) => h.write_u64(self.ty(ty)),
+            Const::Invalid => {}
+        }
+
+        self.consts.insert(key, h.0);
+        h.0
+    }
+}
+
+/// The 64-bit FNV-1a hash, which is deterministic and needs no allocation.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x100_0000_01b3);
+        }
+    }
+}
+
+fn basic_type_tag(basic_type: BasicType) -> char {
+    match basic_type {
+        BasicType::I8 => 'a',
+        BasicType::Bool => 'b',
+        BasicType::Char => 'c',
+        BasicType::F64 => 'd',
+        BasicType::Str => 'e',
+        BasicType::F32 => 'f',
+        BasicType::U8 => 'h',
+        BasicType::Isize => 'i',
+        BasicType::Usize => 'j',
+        BasicType::I32 => 'l',
+        BasicType::U32 => 'm',
+        BasicType::I128 => 'n',
+        BasicType::U128 => 'o',
+        BasicType::Placeholder => 'p',
+        BasicType::I16 => 's',
+        BasicType::U16 => 't',
+        BasicType::Unit => 'u',
+        BasicType::Ellipsis => 'v',
+        BasicType::I64 => 'x',
+        BasicType::U64 => 'y',
+        BasicType::Never => 'z',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast_demangle::AstDemangle;
+    use parse::parse;
+    use parse_demangled::parse_symbol;
+    use alloc::borrow::Cow;
+    use alloc::string::ToString;
+    use alloc::sync::Arc;
+
+    #[test]
+    fn compresses_like_rustc() {
+        for mangled in &["_RINxC3std3fooNtB2_3BarRBb_Bk_E",
+                         "_RNxXC3stdNyNyB2_3foo3FooB9_3bar",
+                         "_RINxC3std3FooKj7b_KbpKBc_E",
+                         "_RNvC7mycrateu10mnchen_Dya"] {
+            assert_eq!(Ok(mangled.to_string()), mangle(&parse(mangled.as_bytes()).unwrap()));
+        }
+    }
+
+    #[test]
+    fn mangles_parsed_text() {
+        let symbol = parse_symbol("<std::vec::Vec<u8> as core::ops::Drop>::drop::<std::vec::Vec<u8>>")
+            .unwrap();
+        let mangled = mangle(&symbol).unwrap();
+
+        assert_eq!("_RINvYINtNtC3std3vec3VechENtNtC4core3ops4Drop4dropB3_E", mangled);
+        assert_eq!(symbol.demangle(), parse(mangled.as_bytes()).unwrap().demangle());
+    }
+
+    #[test]
+    fn disambiguators() {
+        let symbol = parse_symbol("std[1]::foo[2]::<[u8; 3]> @ other[63]").unwrap();
+
+        assert_eq!(Ok("_RINvCs_3stds0_3fooAhj3_ECsZ_5other".to_string()), mangle(&symbol));
+    }
+
+    #[test]
+    fn unencodable_symbols_are_rejected() {
+        let (symbol, _) = ::parse::parse_lenient(b"_RNvNtC3std3vec4pu");
+        assert_eq!(mangle(&symbol), Err(MangleError::Invalid));
+
+        let mut symbol = parse(b"_RNvMs_C3stdh3bar").unwrap();
+        if let Path::Nested { ref mut inner, .. } = symbol.path {
+            if let Path::InherentImpl { ref mut impl_path, .. } = *Arc::make_mut(inner) {
+                impl_path.dis = Some(Base62Number(0));
+            }
+        }
+        assert_eq!(mangle(&symbol), Err(MangleError::ZeroDisambiguator));
+
+        let symbol = parse_symbol("std::3foo").unwrap();
+        assert_eq!(mangle(&symbol), Err(MangleError::LeadingDigit));
+
+        let symbol = Symbol {
+            version: None,
+            path: Path::CrateRoot {
+                id: Ident {
+                    dis: Base62Number(0),
+                    u_ident: UIdent(Cow::Owned(format!("{}\u{10FFFF}", "a".repeat(5000)))),
+                },
+            },
+            instantiating_crate: None,
+        };
+        assert_eq!(mangle(&symbol), Err(MangleError::IdentTooLong));
+    }
+
+    #[test]
+    fn nested_backrefs_are_mangled_in_linear_time() {
+        let mangled = ::generate::nested_backrefs(64);
+        let symbol = parse(mangled.as_bytes()).unwrap();
+        let remangled = mangle(&symbol).unwrap();
+
+        // Comparing the ASTs would expand them, so compare their manglings
+        assert!(remangled.len() <= mangled.len());
+        assert_eq!(mangle(&parse(remangled.as_bytes()).unwrap()), Ok(remangled));
+    }
+}
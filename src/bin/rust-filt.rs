@@ -1,17 +1,200 @@
 
 extern crate std_mangle_rs;
 
-use std_mangle_rs::{mangled_symbol_to_ast, ast_to_demangled_symbol};
+use std_mangle_rs::{ast_to_demangled_symbol, ast_to_mangled_symbol};
+use std_mangle_rs::ast::{Base62Number, Ident, Symbol, UIdent};
+use std_mangle_rs::explain::explain;
+use std_mangle_rs::generate::corpus;
+use std_mangle_rs::int_radix::ascii_digit_to_value;
+use std_mangle_rs::parse::{parse_lenient, parse_with_context, ParseError};
+use std_mangle_rs::parse_demangled::parse_symbol_with_crates;
+use std_mangle_rs::rename::rename_crates;
+use std_mangle_rs::validate::validate;
 
 fn main() {
     let args: Vec<_> = std::env::args().collect();
 
-    if args.len() >= 2 {
+    if args.len() >= 3 && args[1] == "explain" {
 
-        let ast = mangled_symbol_to_ast(&args[1]).unwrap();
+        match explain(args[2].as_bytes()) {
+            Ok(explanation) => print!("{}", explanation),
+            // Only parse again to show where the symbol is malformed
+            Err(_) => {
+                check(&args[2]);
+            }
+        }
+    } else if args.len() >= 3 && args[1] == "mangle" {
+
+        mangle(&args[2..]);
+    } else if args.len() >= 3 && args[1] == "lenient" {
+
+        lenient(&args[2]);
+    } else if args.len() >= 3 && args[1] == "validate" {
+
+        let diagnostics = validate(args[2].as_bytes())
+            .unwrap_or_else(|error| exit_with_diagnostic(&args[2], error));
+        for diagnostic in &diagnostics {
+            println!("{}", diagnostic);
+        }
+        if !diagnostics.is_empty() {
+            std::process::exit(1);
+        }
+    } else if args.len() >= 3 && args[1] == "canonicalize" {
+
+        // The mangler always produces the canonical encoding
+        print_mangled(&check(&args[2]));
+    } else if args.len() >= 5 && args[1] == "rename-crate" {
+
+        rename_crate(&args[2], &args[3], &check(&args[4]));
+    } else if args.len() >= 2 && args[1] == "random" {
+
+        random(&args[2..]);
+    } else if args.len() >= 2 {
+
+        let ast = check(&args[1]);
         let demangled = ast_to_demangled_symbol(&ast);
         println!("{}", demangled);
     } else {
-        eprintln!("no arguments found");
+        exit_with_error("no arguments found");
     }
-}
\ No newline at end of file
+}
+
+/// Parses `mangled`, exiting with a diagnostic if it is not a valid symbol.
+fn check(mangled: &str) -> Symbol<'_> {
+    parse_with_context(mangled.as_bytes())
+        .unwrap_or_else(|error| exit_with_diagnostic(mangled, error))
+}
+
+fn exit_with_diagnostic(mangled: &str, error: ParseError) -> ! {
+    eprint!("{}", error.diagnostic(mangled.as_bytes()));
+    std::process::exit(1);
+}
+
+fn print_mangled(symbol: &Symbol) {
+    match ast_to_mangled_symbol(symbol) {
+        Ok(mangled) => println!("{}", mangled),
+        Err(error) => exit_with_error(&error.to_string()),
+    }
+}
+
+/// Reports an error about the command line, rather than about a symbol.
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    std::process::exit(1);
+}
+
+/// `rust-filt lenient <symbol>` demangles as much of a malformed symbol as
+/// possible, followed by a diagnostic for its first error.
+fn lenient(mangled: &str) {
+    let (symbol, error) = parse_lenient(mangled.as_bytes());
+    println!("{}", ast_to_demangled_symbol(&symbol));
+
+    if let Some(error) = error {
+        eprint!("{}", error.diagnostic(mangled.as_bytes()));
+        std::process::exit(1);
+    }
+}
+
+/// `rust-filt mangle [--crate <name>=<disambiguator>]... <path>`, where the
+/// disambiguator is given in base-62, as it appears in mangled symbols.
+fn mangle(args: &[String]) {
+    let mut crates = Vec::new();
+    let mut path = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "--crate" {
+            let spec = args.next()
+                .unwrap_or_else(|| exit_with_error("--crate needs an argument"));
+            crates.push(parse_crate_disambiguator(spec).unwrap_or_else(|e| exit_with_error(&e)));
+        } else {
+            path = Some(arg);
+        }
+    }
+
+    let path = path.unwrap_or_else(|| exit_with_error("no path to mangle"));
+    let crates: Vec<_> = crates.iter().map(|&(ref name, dis)| (&name[..], dis)).collect();
+
+    let symbol = parse_symbol_with_crates(path, &crates).unwrap_or_else(|e| exit_with_error(&e));
+    print_mangled(&symbol);
+}
+
+/// `rust-filt rename-crate <from> <to> <symbol>`, where `<from>` and `<to>`
+/// are `<name>` or `<name>=<disambiguator>`. Without a disambiguator,
+/// `<from>` matches the crate with any disambiguator and `<to>` keeps it.
+fn rename_crate(from: &str, to: &str, symbol: &Symbol) {
+    let (from_name, from_dis) = parse_crate_spec(from);
+    let (to_name, to_dis) = parse_crate_spec(to);
+
+    let renamed = rename_crates(symbol, |id| {
+        if id.u_ident.0 != from_name || from_dis.is_some_and(|dis| dis != id.dis) {
+            return None;
+        }
+
+        Some(Ident {
+            dis: to_dis.unwrap_or(id.dis),
+            u_ident: UIdent(to_name.clone().into()),
+        })
+    });
+
+    print_mangled(&renamed);
+}
+
+fn parse_crate_spec(spec: &str) -> (String, Option<Base62Number>) {
+    if spec.contains('=') {
+        let (name, dis) = parse_crate_disambiguator(spec).unwrap_or_else(|e| exit_with_error(&e));
+        (name, Some(dis))
+    } else {
+        (spec.to_string(), None)
+    }
+}
+
+/// `rust-filt random [--count <n>] [--seed <s>]` prints random symbols, each
+/// followed by its demangled form, in the format of the test data.
+fn random(args: &[String]) {
+    let mut count = 10;
+    let mut seed = 0;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let value = match args.next().map(|value| value.parse()) {
+            Some(Ok(value)) => value,
+            Some(Err(_)) => exit_with_error("expected a number"),
+            None => exit_with_error(&format!("{} needs an argument", arg)),
+        };
+
+        match &arg[..] {
+            "--count" => count = value as usize,
+            "--seed" => seed = value,
+            _ => exit_with_error(&format!("unknown argument {}", arg)),
+        }
+    }
+
+    for (mangled, demangled) in corpus(seed, count) {
+        println!("{} {}", mangled, demangled);
+    }
+}
+
+fn parse_crate_disambiguator(spec: &str) -> Result<(String, Base62Number), String> {
+    let (name, digits) = match spec.find('=') {
+        Some(eq) => (&spec[..eq], &spec[eq + 1..]),
+        None => return Err("expected <name>=<disambiguator>".to_string()),
+    };
+
+    // `s_` encodes 1 and `s<n>_` encodes n + 2
+    let mut dis: u64 = 1;
+    if !digits.is_empty() {
+        let mut value: u64 = 0;
+        for c in digits.bytes() {
+            let digit = ascii_digit_to_value(c, 62)
+                .ok_or_else(|| format!("invalid base-62 digit `{}`", c as char))?;
+            value = value.checked_mul(62)
+                .and_then(|value| value.checked_add(digit))
+                .ok_or_else(|| format!("disambiguator `{}` is too large", digits))?;
+        }
+        dis = value.checked_add(2)
+            .ok_or_else(|| format!("disambiguator `{}` is too large", digits))?;
+    }
+
+    Ok((name.to_string(), Base62Number(dis)))
+}
@@ -0,0 +1,336 @@
+use ast::*;
+use alloc::borrow::Cow;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// The namespace of a nested path.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Ns {
+    Type,
+    Value,
+    Closure,
+    Shim,
+    /// Any other namespace, given by its tag character.
+    Other(u8),
+}
+
+impl Ns {
+    pub fn namespace(self) -> Namespace {
+        Namespace(match self {
+            Ns::Type => b't',
+            Ns::Value => b'v',
+            Ns::Closure => b'C',
+            Ns::Shim => b'S',
+            Ns::Other(c) => c,
+        })
+    }
+}
+
+/// Assembles a `Symbol` one path component at a time, e.g.
+///
+/// ```
+/// # use std_mangle_rs::ast::Type;
+/// # use std_mangle_rs::builder::{Ns, SymbolBuilder};
+/// let symbol = SymbolBuilder::crate_root("std")
+///     .child(Ns::Type, "Vec")
+///     .generic(vec![Type::u8()])
+///     .child(Ns::Value, "push")
+///     .build();
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SymbolBuilder<'input> {
+    path: Path<'input>,
+    instantiating_crate: Option<Path<'input>>,
+}
+
+impl<'input> SymbolBuilder<'input> {
+    pub fn crate_root<S: Into<Cow<'input, str>>>(name: S) -> SymbolBuilder<'input> {
+        SymbolBuilder::crate_root_with_dis(name, 0)
+    }
+
+    pub fn crate_root_with_dis<S: Into<Cow<'input, str>>>(name: S, dis: u64) -> SymbolBuilder<'input> {
+        SymbolBuilder::from_path(Path::CrateRoot { id: ident(name, dis) })
+    }
+
+    /// Starts with `<self_type as trait_name>`.
+    pub fn trait_def(self_type: Type<'input>, trait_name: Path<'input>) -> SymbolBuilder<'input> {
+        SymbolBuilder::from_path(Path::TraitDef {
+            self_type,
+            trait_name: Arc::new(trait_name),
+        })
+    }
+
+    pub fn from_path(path: Path<'input>) -> SymbolBuilder<'input> {
+        SymbolBuilder {
+            path,
+            instantiating_crate: None,
+        }
+    }
+
+    pub fn child<S: Into<Cow<'input, str>>>(self, ns: Ns, name: S) -> SymbolBuilder<'input> {
+        self.child_with_dis(ns, name, 0)
+    }
+
+    pub fn child_with_dis<S: Into<Cow<'input, str>>>(self,
+                                                     ns: Ns,
+                                                     name: S,
+                                                     dis: u64)
+                                                     -> SymbolBuilder<'input> {
+        self.map_path(|path| Path::Nested {
+            ns: ns.namespace(),
+            inner: Arc::new(path),
+            ident: Some(ident(name, dis)),
+        })
+    }
+
+    /// Appends an anonymous closure, which is demangled as `{closure}[dis]`.
+    pub fn closure(self, dis: u64) -> SymbolBuilder<'input> {
+        self.child_with_dis(Ns::Closure, "", dis)
+    }
+
+    pub fn generic<I, A>(self, args: I) -> SymbolBuilder<'input>
+        where I: IntoIterator<Item = A>,
+              A: Into<GenericArg<'input>>
+    {
+        let args = args.into_iter().map(Into::into).collect();
+
+        self.map_path(|path| Path::Generic {
+            inner: Arc::new(path),
+            args,
+        })
+    }
+
+    /// Turns the path built so far into the impl path of `impl self_type`.
+    pub fn inherent_impl(self, self_type: Type<'input>) -> SymbolBuilder<'input> {
+        self.map_path(|path| Path::InherentImpl {
+            impl_path: impl_path(path),
+            self_type,
+        })
+    }
+
+    /// Turns the path built so far into the impl path of
+    /// `impl trait_name for self_type`.
+    pub fn trait_impl(self, self_type: Type<'input>, trait_name: Path<'input>) -> SymbolBuilder<'input> {
+        self.map_path(|path| Path::TraitImpl {
+            impl_path: impl_path(path),
+            self_type,
+            trait_name: Arc::new(trait_name),
+        })
+    }
+
+    pub fn instantiating_crate<S: Into<Cow<'input, str>>>(mut self, name: S) -> SymbolBuilder<'input> {
+        self.instantiating_crate = Some(Path::CrateRoot { id: ident(name, 0) });
+        self
+    }
+
+    pub fn path(self) -> Path<'input> {
+        self.path
+    }
+
+    pub fn build(self) -> Symbol<'input> {
+        Symbol {
+            version: None,
+            path: self.path,
+            instantiating_crate: self.instantiating_crate,
+        }
+    }
+
+    fn map_path<F>(self, f: F) -> SymbolBuilder<'input>
+        where F: FnOnce(Path<'input>) -> Path<'input>
+    {
+        SymbolBuilder {
+            path: f(self.path),
+            instantiating_crate: self.instantiating_crate,
+        }
+    }
+}
+
+fn ident<'input, S: Into<Cow<'input, str>>>(name: S, dis: u64) -> Ident<'input> {
+    Ident {
+        dis: Base62Number(dis),
+        u_ident: UIdent(name.into()),
+    }
+}
+
+fn impl_path(path: Path<'_>) -> ImplPath<'_> {
+    ImplPath {
+        dis: None,
+        path: Arc::new(path),
+    }
+}
+
+macro_rules! basic_type_constructors {
+    ($($name:ident => $basic_type:ident,)*) => {
+        $(
+            pub fn $name() -> Type<'input> {
+                Type::BasicType(BasicType::$basic_type)
+            }
+        )*
+    }
+}
+
+impl<'input> Type<'input> {
+    basic_type_constructors! {
+        bool => Bool,
+        char => Char,
+        str => Str,
+        unit => Unit,
+        i8 => I8,
+        i16 => I16,
+        i32 => I32,
+        i64 => I64,
+        i128 => I128,
+        isize => Isize,
+        u8 => U8,
+        u16 => U16,
+        u32 => U32,
+        u64 => U64,
+        u128 => U128,
+        usize => Usize,
+        f32 => F32,
+        f64 => F64,
+        never => Never,
+        placeholder => Placeholder,
+    }
+
+    pub fn named(path: Path<'input>) -> Type<'input> {
+        Type::Named(Arc::new(path))
+    }
+
+    /// `[ty; len]`
+    pub fn array(ty: Type<'input>, len: u64) -> Type<'input> {
+        Type::Array(Arc::new(ty), Arc::new(Const::Value(Type::usize(), len)))
+    }
+
+    pub fn slice(ty: Type<'input>) -> Type<'input> {
+        Type::Slice(Arc::new(ty))
+    }
+
+    pub fn tuple(components: Vec<Type<'input>>) -> Type<'input> {
+        Type::Tuple(components)
+    }
+
+    pub fn reference(ty: Type<'input>) -> Type<'input> {
+        Type::Ref(None, Arc::new(ty))
+    }
+
+    pub fn reference_mut(ty: Type<'input>) -> Type<'input> {
+        Type::RefMut(None, Arc::new(ty))
+    }
+
+    pub fn ptr_const(ty: Type<'input>) -> Type<'input> {
+        Type::RawPtrConst(Arc::new(ty))
+    }
+
+    pub fn ptr_mut(ty: Type<'input>) -> Type<'input> {
+        Type::RawPtrMut(Arc::new(ty))
+    }
+
+    pub fn fn_ptr(fn_sig: FnSig<'input>) -> Type<'input> {
+        Type::Fn(Arc::new(fn_sig))
+    }
+
+    /// `dyn trait_name`, with the default lifetime.
+    pub fn dyn_trait(trait_name: Path<'input>) -> Type<'input> {
+        Type::DynTrait(Arc::new(DynBounds {
+            binder: Binder { count: Base62Number(0) },
+            traits: vec![DynTrait {
+                path: trait_name,
+                assoc_type_bindings: vec![],
+            }],
+        }), Lifetime { debruijn_index: Base62Number(0) })
+    }
+}
+
+impl<'input> FnSig<'input> {
+    /// A safe function signature with the Rust ABI.
+    pub fn new(param_types: Vec<Type<'input>>, return_type: Type<'input>) -> FnSig<'input> {
+        FnSig {
+            binder: Binder { count: Base62Number(0) },
+            is_unsafe: false,
+            abi: None,
+            param_types,
+            return_type,
+        }
+    }
+
+    pub fn with_unsafe(mut self) -> FnSig<'input> {
+        self.is_unsafe = true;
+        self
+    }
+
+    pub fn with_abi(mut self, abi: Abi<'input>) -> FnSig<'input> {
+        self.abi = Some(abi);
+        self
+    }
+}
+
+impl<'input> From<Type<'input>> for GenericArg<'input> {
+    fn from(ty: Type<'input>) -> GenericArg<'input> {
+        GenericArg::Type(ty)
+    }
+}
+
+impl<'input> From<Const<'input>> for GenericArg<'input> {
+    fn from(k: Const<'input>) -> GenericArg<'input> {
+        GenericArg::Const(k)
+    }
+}
+
+impl<'input> From<Lifetime> for GenericArg<'input> {
+    fn from(lifetime: Lifetime) -> GenericArg<'input> {
+        GenericArg::Lifetime(lifetime)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast_demangle::AstDemangle;
+    use parse::parse;
+
+    #[test]
+    fn builds_same_ast_as_parser() {
+        let symbol = SymbolBuilder::crate_root("std")
+            .child(Ns::Type, "Vec")
+            .generic(vec![Type::u8()])
+            .child(Ns::Value, "push")
+            .build();
+
+        assert_eq!(parse(b"_RNvINtC3std3VechE4push").unwrap(), symbol);
+        assert_eq!("std::Vec<u8>::push", symbol.demangle());
+    }
+
+    #[test]
+    fn impls_closures_and_instantiating_crate() {
+        let fmt = SymbolBuilder::crate_root("core")
+            .child(Ns::Type, "fmt")
+            .child(Ns::Type, "Debug")
+            .path();
+
+        let symbol = SymbolBuilder::crate_root_with_dis("foo", 3)
+            .trait_impl(Type::named(SymbolBuilder::crate_root("foo").child(Ns::Type, "Bar").path()),
+                        fmt)
+            .child(Ns::Value, "fmt")
+            .closure(2)
+            .instantiating_crate("baz")
+            .build();
+
+        assert_eq!("<foo::Bar as core::fmt::Debug>::fmt::{closure}[2] @ baz",
+                   symbol.demangle());
+    }
+
+    #[test]
+    fn type_constructors() {
+        let sig = FnSig::new(vec![Type::reference(Type::str()), Type::array(Type::i32(), 4)],
+                             Type::bool())
+            .with_unsafe()
+            .with_abi(Abi::C);
+
+        let ty = Type::ptr_mut(Type::tuple(vec![Type::fn_ptr(sig),
+                                                Type::slice(Type::reference_mut(Type::char()))]));
+
+        assert_eq!("*mut (unsafe extern \"C\" fn(&str,[i32; 4]) -> bool,[&mut char])",
+                   ty.demangle());
+    }
+}
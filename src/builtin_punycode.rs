@@ -0,0 +1,307 @@
+//! A built-in punycode implementation. The decoder is used in place of
+//! `unic-idna-punycode` when the crate is built without the `std` feature,
+//! and by the streaming demangler, which decodes into a fixed-size buffer
+//! instead of the heap. The encoder is used by the mangler.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::char;
+
+const BASE: u32 = 36;
+const T_MIN: u32 = 1;
+const T_MAX: u32 = 26;
+const SKEW: u32 = 38;
+const DAMP: u32 = 700;
+const INITIAL_BIAS: u32 = 72;
+const INITIAL_N: u32 = 128;
+
+/// Decodes an RFC 3492 punycode string.
+#[cfg(any(test, not(feature = "std")))]
+pub fn decode_to_string(input: &str) -> Option<String> {
+    let (basic, extended) = match input.rfind('-') {
+        Some(index) => (&input[..index], &input[index + 1..]),
+        None => ("", input),
+    };
+
+    // Every char of the output takes up at least one byte of the input.
+    let mut output = vec!['\0'; input.len()];
+    let len = decode_into(basic.as_bytes(), extended.as_bytes(), rfc_digit_value, &mut output)?;
+
+    Some(output[..len].iter().collect())
+}
+
+/// Decodes the bytes of a punycode identifier in a v0 symbol into `output`,
+/// returning the number of chars written. Such identifiers use `_` as the
+/// delimiter and `A`-`J` in place of the digits `0`-`9`. `output` must have
+/// room for at least `ident.len()` chars.
+pub fn decode_v0_ident_into(ident: &[u8], output: &mut [char]) -> Option<usize> {
+    let (basic, extended) = match ident.iter().rposition(|&c| c == b'_') {
+        Some(index) => (&ident[..index], &ident[index + 1..]),
+        None => (&ident[..0], ident),
+    };
+
+    decode_into(basic, extended, v0_digit_value, output)
+}
+
+#[cfg(any(test, not(feature = "std")))]
+fn rfc_digit_value(c: u8) -> Option<u32> {
+    Some(match c {
+        b'a' ..= b'z' => c - b'a',
+        b'A' ..= b'Z' => c - b'A',
+        b'0' ..= b'9' => c - b'0' + 26,
+        _ => return None,
+    } as u32)
+}
+
+fn v0_digit_value(c: u8) -> Option<u32> {
+    // Like `charset::decode_punycode_ident`, which maps `A`-`J` to `0`-`9`
+    // and leaves the remaining upper case letters and the digits themselves
+    // to the RFC decoder.
+    Some(match c {
+        b'a' ..= b'z' => c - b'a',
+        b'A' ..= b'J' => c - b'A' + 26,
+        b'K' ..= b'Z' => c - b'A',
+        b'0' ..= b'9' => c - b'0' + 26,
+        _ => return None,
+    } as u32)
+}
+
+fn decode_into(basic: &[u8],
+               extended: &[u8],
+               digit_value: fn(u8) -> Option<u32>,
+               output: &mut [char])
+               -> Option<usize> {
+    if !basic.is_ascii() || basic.len() > output.len() {
+        return None;
+    }
+
+    for (out, &c) in output.iter_mut().zip(basic) {
+        *out = c as char;
+    }
+
+    let mut len = basic.len();
+    let mut extended = extended.iter().peekable();
+
+    let mut n = INITIAL_N;
+    let mut i: u32 = 0;
+    let mut bias = INITIAL_BIAS;
+
+    while extended.peek().is_some() {
+        let old_i = i;
+        let mut weight: u32 = 1;
+        let mut k = BASE;
+
+        loop {
+            let digit = digit_value(*extended.next()?)?;
+
+            i = i.checked_add(digit.checked_mul(weight)?)?;
+
+            let t = if k <= bias {
+                T_MIN
+            } else if k >= bias + T_MAX {
+                T_MAX
+            } else {
+                k - bias
+            };
+
+            if digit < t {
+                break;
+            }
+
+            weight = weight.checked_mul(BASE - t)?;
+            k += BASE;
+        }
+
+        if len == output.len() {
+            return None;
+        }
+
+        let num_points = len as u32 + 1;
+        bias = adapt(i - old_i, num_points, old_i == 0);
+        n = n.checked_add(i / num_points)?;
+        i %= num_points;
+
+        let index = i as usize;
+        output.copy_within(index .. len, index + 1);
+        output[index] = char::from_u32(n)?;
+        len += 1;
+        i += 1;
+    }
+
+    Some(len)
+}
+
+/// Encodes a non-ASCII identifier the way it is stored in a v0 symbol,
+/// i.e. with `_` as the delimiter and `A`-`J` in place of the digits
+/// `0`-`9`. This is the inverse of `decode_v0_ident_into`.
+pub fn encode_v0_ident(ident: &str) -> Option<String> {
+    let input: Vec<char> = ident.chars().collect();
+    let mut output: String = input.iter().filter(|c| c.is_ascii()).collect();
+
+    let num_basic = output.len() as u32;
+    if num_basic > 0 {
+        output.push('_');
+    }
+
+    let mut n = INITIAL_N;
+    let mut delta: u32 = 0;
+    let mut bias = INITIAL_BIAS;
+    let mut num_handled = num_basic;
+
+    while (num_handled as usize) < input.len() {
+        let m = input.iter().map(|&c| c as u32).filter(|&c| c >= n).min()?;
+
+        delta = delta.checked_add((m - n).checked_mul(num_handled + 1)?)?;
+        n = m;
+
+        for &c in &input {
+            let c = c as u32;
+
+            if c < n {
+                delta = delta.checked_add(1)?;
+            }
+
+            if c == n {
+                let mut q = delta;
+                let mut k = BASE;
+
+                loop {
+                    let t = if k <= bias {
+                        T_MIN
+                    } else if k >= bias + T_MAX {
+                        T_MAX
+                    } else {
+                        k - bias
+                    };
+
+                    if q < t {
+                        break;
+                    }
+
+                    output.push(v0_digit(t + (q - t) % (BASE - t)));
+                    q = (q - t) / (BASE - t);
+                    k += BASE;
+                }
+
+                output.push(v0_digit(q));
+                bias = adapt(delta, num_handled + 1, num_handled == num_basic);
+                delta = 0;
+                num_handled += 1;
+            }
+        }
+
+        delta = delta.checked_add(1)?;
+        n += 1;
+    }
+
+    Some(output)
+}
+
+fn v0_digit(value: u32) -> char {
+    let value = value as u8;
+
+    if value < 26 {
+        (b'a' + value) as char
+    } else {
+        (b'A' + value - 26) as char
+    }
+}
+
+fn adapt(delta: u32, num_points: u32, first_time: bool) -> u32 {
+    let mut delta = if first_time { delta / DAMP } else { delta / 2 };
+    delta += delta / num_points;
+
+    let mut k = 0;
+    while delta > ((BASE - T_MIN) * T_MAX) / 2 {
+        delta /= BASE - T_MIN;
+        k += BASE;
+    }
+
+    k + (((BASE - T_MIN + 1) * delta) / (delta + SKEW))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::borrow::ToOwned;
+    #[cfg(feature = "std")]
+    use charset;
+
+    #[test]
+    fn decoder_matches_rfc_examples() {
+        assert_eq!(Some("münchen".to_owned()), decode_to_string("mnchen-3ya"));
+        assert_eq!(Some("bücher".to_owned()), decode_to_string("bcher-kva"));
+        assert_eq!(Some("他们为什么不说中文".to_owned()),
+                   decode_to_string("ihqwcrb4cv8a8dqg056pqjye"));
+        assert_eq!(Some("abc".to_owned()), decode_to_string("abc-"));
+        assert_eq!(None, decode_to_string("abc-!"));
+        assert_eq!(None, decode_to_string("99999999999"));
+    }
+
+    #[test]
+    fn v0_idents_need_room_for_their_length() {
+        let mut output = ['\0'; 7];
+        assert_eq!(Some(7), decode_v0_ident_into(b"mnchen_Dya", &mut output));
+        assert_eq!("münchen", output.iter().collect::<String>());
+
+        let mut output = ['\0'; 6];
+        assert_eq!(None, decode_v0_ident_into(b"mnchen_Dya", &mut output));
+    }
+
+    #[test]
+    fn v0_encoding() {
+        assert_eq!(Some("mnchen_Dya".to_owned()), encode_v0_ident("münchen"));
+        assert_eq!(Some("ihqwcrbEcvIaIdqgAFGpqjye".to_owned()), encode_v0_ident("他们为什么不说中文"));
+    }
+
+    quickcheck! {
+        fn v0_encoding_round_trips(s: String) -> bool {
+            if s.is_ascii() {
+                return true;
+            }
+
+            let encoded = encode_v0_ident(&s).unwrap();
+            let mut output = vec!['\0'; encoded.len()];
+            let len = decode_v0_ident_into(encoded.as_bytes(), &mut output).unwrap();
+
+            output[..len].iter().collect::<String>() == s
+        }
+    }
+
+    // The builtin decoder is compared against `unic-idna-punycode`, which is
+    // only available with the `std` feature.
+    #[cfg(feature = "std")]
+    quickcheck! {
+        fn decoder_agrees_with_unic(s: String) -> bool {
+            match ::punycode::encode_str(&s) {
+                Some(encoded) => decode_to_string(&encoded) == Some(s),
+                None => true,
+            }
+        }
+
+        fn v0_decoder_agrees_with_charset(s: String) -> bool {
+            let encoded = match ::punycode::encode_str(&s) {
+                Some(encoded) => encoded,
+                None => return true,
+            };
+
+            // Turn the RFC form into the one used in v0 symbols
+            let mut ident = encoded.into_bytes();
+            if let Some(index) = ident.iter().rposition(|&c| c == b'-') {
+                ident[index] = b'_';
+            }
+            let delimiter = ident.iter().rposition(|&c| c == b'_').map_or(0, |i| i + 1);
+            for c in &mut ident[delimiter..] {
+                if c.is_ascii_digit() {
+                    *c = *c - b'0' + b'A';
+                }
+            }
+
+            let mut output = vec!['\0'; ident.len()];
+            let decoded = decode_v0_ident_into(&ident, &mut output)
+                .map(|len| output[..len].iter().collect::<String>());
+
+            decoded == charset::decode_punycode_ident(&ident).ok()
+        }
+    }
+}
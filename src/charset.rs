@@ -1,4 +1,6 @@
 use punycode;
+use alloc::borrow::ToOwned;
+use alloc::string::String;
 
 pub fn decode_punycode_ident(ident_bytes: &[u8]) -> Result<String, String> {
     if ident_bytes.iter().any(|b| !b.is_ascii()) {
@@ -21,12 +23,10 @@ pub fn decode_punycode_ident(ident_bytes: &[u8]) -> Result<String, String> {
 
     match punycode::decode_to_string(&ident_str) {
         Some(s) => Ok(s),
-        None => {
-            return Err(format!(
-                "Could not decode punycode-encoded ident '{}'.",
-                ident_str
-            ));
-        }
+        None => Err(format!(
+            "Could not decode punycode-encoded ident '{}'.",
+            ident_str
+        )),
     }
 }
 
@@ -0,0 +1,198 @@
+use parse::{self, Production, Span};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// A lossless concrete syntax tree of a mangled symbol. Unlike the AST,
+/// back-references are kept as explicit elements instead of being replaced
+/// by a copy of their target, and every byte of the input is retained, so
+/// `to_bytes()` reproduces the input exactly.
+///
+/// All offsets in the tree refer to the input the tree was parsed from.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Cst {
+    pub elements: Vec<Element>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Element {
+    Node(Node),
+    Backref(Backref),
+    /// Bytes that are not part of any child production, like tags,
+    /// numbers and identifier text.
+    Token(Vec<u8>),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Node {
+    pub production: Production,
+    /// The offset of the node's first byte in the input.
+    pub start: usize,
+    pub children: Vec<Element>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Backref {
+    /// The production found at the target of the back-reference.
+    pub production: Production,
+    /// The offset of the target in the input (i.e. including the `_R`
+    /// prefix, unlike the encoded number).
+    pub target: usize,
+    /// The encoded back-reference, e.g. `B4_`.
+    pub bytes: Vec<u8>,
+}
+
+/// Parses a mangled symbol into a concrete syntax tree.
+pub fn parse_cst(input: &[u8]) -> Result<Cst, String> {
+    let (_, spans) = parse::parse_with_literal_spans(input)?;
+
+    let mut index = 0;
+    let elements = build_elements(input, &spans, &mut index, 0, 0 .. input.len());
+
+    Ok(Cst { elements })
+}
+
+impl Cst {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_elements(&self.elements, &mut out);
+        out
+    }
+
+    /// Finds the node a back-reference points to. For a back-reference to a
+    /// named <type> this may be a <path> node if the target only occurs in
+    /// path position.
+    pub fn resolve(&self, backref: &Backref) -> Option<&Node> {
+        find_node(&self.elements, backref.production, backref.target)
+    }
+}
+
+impl Node {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_elements(&self.children, &mut out);
+        out
+    }
+}
+
+fn build_elements(input: &[u8],
+                  spans: &[Span],
+                  index: &mut usize,
+                  depth: usize,
+                  range: Range<usize>)
+                  -> Vec<Element> {
+    let mut elements = Vec::new();
+    let mut pos = range.start;
+
+    while *index < spans.len() && spans[*index].depth == depth {
+        let span = &spans[*index];
+        *index += 1;
+
+        if span.start > pos {
+            elements.push(Element::Token(input[pos .. span.start].to_vec()));
+        }
+
+        if span.production == Production::Backref {
+            // The production at the target is always recorded right after
+            // the back-reference itself.
+            let target = &spans[*index];
+
+            elements.push(Element::Backref(Backref {
+                production: target.production,
+                target: target.start,
+                bytes: input[span.start .. span.end].to_vec(),
+            }));
+
+            while *index < spans.len() && spans[*index].depth > depth {
+                *index += 1;
+            }
+        } else {
+            elements.push(Element::Node(Node {
+                production: span.production,
+                start: span.start,
+                children: build_elements(input, spans, index, depth + 1, span.start .. span.end),
+            }));
+        }
+
+        pos = span.end;
+    }
+
+    if range.end > pos {
+        elements.push(Element::Token(input[pos .. range.end].to_vec()));
+    }
+
+    elements
+}
+
+fn write_elements(elements: &[Element], out: &mut Vec<u8>) {
+    for element in elements {
+        match *element {
+            Element::Node(ref node) => write_elements(&node.children, out),
+            Element::Backref(ref backref) => out.extend_from_slice(&backref.bytes),
+            Element::Token(ref bytes) => out.extend_from_slice(bytes),
+        }
+    }
+}
+
+fn find_node(elements: &[Element], production: Production, start: usize) -> Option<&Node> {
+    for element in elements {
+        if let Element::Node(ref node) = *element {
+            let matches = node.production == production ||
+                          (production == Production::Type && node.production == Production::Path);
+
+            if matches && node.start == start {
+                return Some(node);
+            }
+
+            if let Some(node) = find_node(&node.children, production, start) {
+                return Some(node);
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_bytes_reproduces_test_data() {
+        for mangled in ::test_data::valid_symbols() {
+            let mangled = mangled.as_bytes();
+            let cst = parse_cst(mangled).unwrap();
+            assert_eq!(mangled, &cst.to_bytes()[..]);
+        }
+    }
+
+    #[test]
+    fn trailing_bytes_are_rejected() {
+        assert!(parse_cst(b"_RNvC3foo3barC3bazXYZ").is_err());
+    }
+
+    #[test]
+    fn backrefs_are_explicit() {
+        let cst = parse_cst(b"_RINxC3std3fooB0_E").unwrap();
+
+        let generic = match cst.elements[1] {
+            Element::Node(ref node) => node,
+            ref other => panic!("unexpected {:?}", other),
+        };
+
+        let backref = match generic.children[2] {
+            Element::Backref(ref backref) => backref,
+            ref other => panic!("unexpected {:?}", other),
+        };
+
+        // A back-reference in type position always targets a <type>
+        assert_eq!(Backref {
+            production: Production::Type,
+            target: 3,
+            bytes: b"B0_".to_vec(),
+        }, *backref);
+
+        let target = cst.resolve(backref).unwrap();
+        assert_eq!(b"NxC3std3foo".to_vec(), target.to_bytes());
+    }
+}
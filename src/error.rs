@@ -1,4 +1,6 @@
-use std::fmt::{Display, Write};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{Display, Write};
 
 pub fn expected<T>(
     expected_chars: &str,
@@ -8,7 +10,7 @@ pub fn expected<T>(
 ) -> Result<T, String> {
     let expected_chars = expected_chars.chars().collect::<Vec<_>>();
 
-    assert!(expected_chars.len() > 0);
+    assert!(!expected_chars.is_empty());
 
     let mut message = "Expected ".to_string();
 
@@ -22,8 +24,8 @@ pub fn expected<T>(
             char_to_str(expected_chars[1])
         ).unwrap();
     } else {
-        for i in 0..expected_chars.len() - 1 {
-            write!(message, "{}, ", char_to_str(expected_chars[i])).unwrap();
+        for &c in &expected_chars[..expected_chars.len() - 1] {
+            write!(message, "{}, ", char_to_str(c)).unwrap();
         }
 
         write!(
@@ -44,9 +46,11 @@ pub fn expected<T>(
     Err(message)
 }
 
-fn char_to_str(c: char) -> String {
+pub fn char_to_str(c: char) -> String {
     match c {
         '#' => "digit".to_string(),
+        // `parse::EOT`
+        '\u{5}' => "end of input".to_string(),
         c => format!("'{}'", c),
     }
 }
@@ -0,0 +1,174 @@
+use parse::{self, Production, Span};
+use stream_demangle::demangle_production_at;
+use alloc::string::{String, ToString};
+use core::fmt::Write;
+
+/// Generates an annotated, byte-by-byte breakdown of a mangled symbol. Every
+/// production gets its own line, showing the bytes it was parsed from at
+/// their original column, followed by a description of what they encode.
+/// Back-references are shown as the offset of their target rather than
+/// what they refer to, so the breakdown stays proportional in size to the
+/// symbol.
+pub fn explain(mangled: &[u8]) -> Result<String, String> {
+    let (_, spans) = parse::parse_with_literal_spans(mangled)?;
+
+    let label_column = mangled.len() + 2;
+    let mut out = String::new();
+
+    writeln!(out, "{}", String::from_utf8_lossy(mangled)).unwrap();
+    writeln!(out, "{:<width$}Rust symbol prefix", "_R", width = label_column).unwrap();
+
+    let mut inside_backref = None;
+
+    for (index, span) in spans.iter().enumerate() {
+        if let Some(depth) = inside_backref {
+            if span.depth > depth {
+                continue;
+            }
+            inside_backref = None;
+        }
+
+        if span.production == Production::Backref {
+            inside_backref = Some(span.depth);
+        } else if let Some(child) = spans.get(index + 1) {
+            // A production covering exactly the same bytes as its first child
+            // (e.g. a named <type> and its <path>) is described by the child.
+            if child.depth == span.depth + 1 && child.start == span.start &&
+               child.end == span.end {
+                continue;
+            }
+        }
+
+        let bytes = String::from_utf8_lossy(&mangled[span.start .. span.end]);
+        let bytes = format!("{:>width$}", bytes, width = span.end);
+
+        writeln!(out,
+                 "{:<width$}{}",
+                 bytes,
+                 describe(mangled, &spans, index)?,
+                 width = label_column).unwrap();
+    }
+
+    Ok(out)
+}
+
+fn describe(mangled: &[u8], spans: &[Span], index: usize) -> Result<String, String> {
+    let span = &spans[index];
+    let tag = mangled[span.start];
+
+    Ok(match span.production {
+        Production::Path => {
+            let kind = match tag {
+                b'C' => "crate root".to_string(),
+                b'M' => "inherent impl".to_string(),
+                b'X' => "trait impl".to_string(),
+                b'Y' => "trait definition".to_string(),
+                b'N' => format!("nested path in {}", namespace_name(mangled[span.start + 1])),
+                _ => "generic path".to_string(),
+            };
+
+            format!("{} `{}`", kind, demangle_at(mangled, Production::Path, span.start)?)
+        }
+        Production::Type => {
+            let kind = match tag {
+                b'A' => "array type",
+                b'S' => "slice type",
+                b'T' => "tuple type",
+                b'R' => "shared reference type",
+                b'Q' => "mutable reference type",
+                b'P' => "const pointer type",
+                b'O' => "mut pointer type",
+                b'F' => "function pointer type",
+                b'D' => "trait object type",
+                _ => "basic type",
+            };
+
+            format!("{} `{}`", kind, demangle_at(mangled, Production::Type, span.start)?)
+        }
+        Production::Const => {
+            format!("constant `{}`", demangle_at(mangled, Production::Const, span.start)?)
+        }
+        Production::Ident | Production::UIdent => {
+            let kind = match tag {
+                b's' => "disambiguated identifier",
+                b'u' => "punycode identifier",
+                _ => "identifier",
+            };
+
+            format!("{} `{}`", kind, demangle_at(mangled, Production::Ident, span.start)?)
+        }
+        Production::Backref => {
+            let target = &spans[index + 1];
+
+            format!("back-reference to the {} at offset {}",
+                    target.production.name(),
+                    target.start)
+        }
+        Production::Namespace => namespace_name(tag),
+        Production::ImplPath => "impl path".to_string(),
+        Production::FnSig => "function signature".to_string(),
+        Production::Abi => "ABI".to_string(),
+        Production::DynBounds => "trait object bounds".to_string(),
+        Production::DynTrait => "trait object bound".to_string(),
+        Production::DynTraitAssocBinding => "associated type binding".to_string(),
+        Production::Lifetime => "lifetime".to_string(),
+        Production::Binder => "binder".to_string(),
+        Production::Disambiguator => "disambiguator".to_string(),
+    })
+}
+
+fn namespace_name(ns: u8) -> String {
+    match ns {
+        b'C' => "closure namespace".to_string(),
+        b'S' => "shim namespace".to_string(),
+        b't' => "type namespace".to_string(),
+        b'v' => "value namespace".to_string(),
+        c => format!("namespace '{}'", c as char),
+    }
+}
+
+fn demangle_at(mangled: &[u8], production: Production, pos: usize) -> Result<String, String> {
+    let mut out = String::new();
+    demangle_production_at(mangled, pos, production, &mut out).map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explain_nested_path() {
+        let expected = "\
+_RNvC3std3foo
+_R             Rust symbol prefix
+  NvC3std3foo  nested path in value namespace `std::foo`
+   v           value namespace
+    C3std      crate root `std`
+     3std      identifier `std`
+         3foo  identifier `foo`
+";
+
+        assert_eq!(expected, explain(b"_RNvC3std3foo").unwrap());
+    }
+
+    #[test]
+    fn explain_backref() {
+        let explanation = explain(b"_RINxC3std3fooNyB0_3BarE").unwrap();
+
+        assert!(explanation.contains("\n              NyB0_3Bar   nested path in namespace 'y' \
+                                      `{B0_ -> 3}::Bar`\n"),
+                "{}", explanation);
+        assert!(explanation.contains("\n                B0_       back-reference to the <path> \
+                                      at offset 3\n"),
+                "{}", explanation);
+    }
+
+    #[test]
+    fn nested_backrefs_are_not_expanded() {
+        let mangled = ::generate::nested_backrefs(64);
+        let explanation = explain(mangled.as_bytes()).unwrap();
+
+        assert!(explanation.lines().all(|line| line.len() < 8 * mangled.len()));
+    }
+}
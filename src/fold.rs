@@ -0,0 +1,398 @@
+//! Rebuilding the AST with some of its nodes replaced. The methods of
+//! `Folder` rebuild a node from its folded children by default, via the
+//! `walk_*` functions, so that a transformation only needs to override the
+//! methods for the nodes it changes.
+
+use ast::*;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+
+pub trait Folder<'input> {
+    fn fold_symbol(&mut self, symbol: &Symbol<'input>) -> Symbol<'input> {
+        walk_symbol(self, symbol)
+    }
+
+    fn fold_path(&mut self, path: &Path<'input>) -> Path<'input> {
+        walk_path(self, path)
+    }
+
+    fn fold_impl_path(&mut self, impl_path: &ImplPath<'input>) -> ImplPath<'input> {
+        walk_impl_path(self, impl_path)
+    }
+
+    fn fold_ident(&mut self, ident: &Ident<'input>) -> Ident<'input> {
+        ident.clone()
+    }
+
+    fn fold_generic_arg(&mut self, arg: &GenericArg<'input>) -> GenericArg<'input> {
+        walk_generic_arg(self, arg)
+    }
+
+    fn fold_lifetime(&mut self, lifetime: &Lifetime) -> Lifetime {
+        lifetime.clone()
+    }
+
+    fn fold_binder(&mut self, binder: &Binder) -> Binder {
+        binder.clone()
+    }
+
+    fn fold_type(&mut self, ty: &Type<'input>) -> Type<'input> {
+        walk_type(self, ty)
+    }
+
+    fn fold_fn_sig(&mut self, fn_sig: &FnSig<'input>) -> FnSig<'input> {
+        walk_fn_sig(self, fn_sig)
+    }
+
+    fn fold_dyn_bounds(&mut self, bounds: &DynBounds<'input>) -> DynBounds<'input> {
+        walk_dyn_bounds(self, bounds)
+    }
+
+    fn fold_dyn_trait(&mut self, tr: &DynTrait<'input>) -> DynTrait<'input> {
+        walk_dyn_trait(self, tr)
+    }
+
+    fn fold_const(&mut self, k: &Const<'input>) -> Const<'input> {
+        walk_const(self, k)
+    }
+
+    /// Where the shared subtrees that have been folded are remembered. With
+    /// the default, `None`, every reference to a shared subtree gets its own
+    /// copy; return a `SharedFolds` to fold each shared subtree only once and
+    /// share the result between all references to it.
+    fn shared_folds(&mut self) -> Option<&mut SharedFolds<'input>> {
+        None
+    }
+
+    /// Called for the children behind an `Arc`, which the parser shares
+    /// between all back-references to the same node.
+    fn fold_shared_path(&mut self, path: &Arc<Path<'input>>) -> Arc<Path<'input>> {
+        if let Some(folded) = self.shared_folds().and_then(|folds| folds.paths.get(path)) {
+            return folded;
+        }
+
+        let folded = self.fold_path(path);
+        match self.shared_folds() {
+            Some(folds) => folds.paths.insert(path, folded),
+            None => Arc::new(folded),
+        }
+    }
+
+    fn fold_shared_type(&mut self, ty: &Arc<Type<'input>>) -> Arc<Type<'input>> {
+        if let Some(folded) = self.shared_folds().and_then(|folds| folds.types.get(ty)) {
+            return folded;
+        }
+
+        let folded = self.fold_type(ty);
+        match self.shared_folds() {
+            Some(folds) => folds.types.insert(ty, folded),
+            None => Arc::new(folded),
+        }
+    }
+
+    fn fold_shared_const(&mut self, k: &Arc<Const<'input>>) -> Arc<Const<'input>> {
+        if let Some(folded) = self.shared_folds().and_then(|folds| folds.consts.get(k)) {
+            return folded;
+        }
+
+        let folded = self.fold_const(k);
+        match self.shared_folds() {
+            Some(folds) => folds.consts.insert(k, folded),
+            None => Arc::new(folded),
+        }
+    }
+
+    fn fold_shared_fn_sig(&mut self, fn_sig: &Arc<FnSig<'input>>) -> Arc<FnSig<'input>> {
+        if let Some(folded) = self.shared_folds().and_then(|folds| folds.fn_sigs.get(fn_sig)) {
+            return folded;
+        }
+
+        let folded = self.fold_fn_sig(fn_sig);
+        match self.shared_folds() {
+            Some(folds) => folds.fn_sigs.insert(fn_sig, folded),
+            None => Arc::new(folded),
+        }
+    }
+
+    fn fold_shared_dyn_bounds(&mut self,
+                              bounds: &Arc<DynBounds<'input>>)
+                              -> Arc<DynBounds<'input>> {
+        if let Some(folded) = self.shared_folds().and_then(|folds| folds.dyn_bounds.get(bounds)) {
+            return folded;
+        }
+
+        let folded = self.fold_dyn_bounds(bounds);
+        match self.shared_folds() {
+            Some(folds) => folds.dyn_bounds.insert(bounds, folded),
+            None => Arc::new(folded),
+        }
+    }
+}
+
+/// What the `Arc`s a folder has been through were folded into, by address.
+/// The addresses are only meaningful while the folded symbol is alive.
+#[derive(Default, Debug)]
+pub struct SharedFolds<'input> {
+    paths: FoldedNodes<Path<'input>>,
+    types: FoldedNodes<Type<'input>>,
+    consts: FoldedNodes<Const<'input>>,
+    fn_sigs: FoldedNodes<FnSig<'input>>,
+    dyn_bounds: FoldedNodes<DynBounds<'input>>,
+}
+
+#[derive(Debug)]
+struct FoldedNodes<T>(BTreeMap<usize, Arc<T>>);
+
+impl<T> Default for FoldedNodes<T> {
+    fn default() -> Self {
+        FoldedNodes(BTreeMap::new())
+    }
+}
+
+impl<T> FoldedNodes<T> {
+    fn get(&self, node: &Arc<T>) -> Option<Arc<T>> {
+        self.0.get(&(Arc::as_ptr(node) as usize)).cloned()
+    }
+
+    fn insert(&mut self, node: &Arc<T>, folded: T) -> Arc<T> {
+        let folded = Arc::new(folded);
+        self.0.insert(Arc::as_ptr(node) as usize, folded.clone());
+        folded
+    }
+}
+
+pub fn walk_symbol<'input, F>(folder: &mut F, symbol: &Symbol<'input>) -> Symbol<'input>
+    where F: Folder<'input> + ?Sized
+{
+    Symbol {
+        version: symbol.version,
+        path: folder.fold_path(&symbol.path),
+        instantiating_crate: symbol.instantiating_crate.as_ref().map(|path| folder.fold_path(path)),
+    }
+}
+
+pub fn walk_path<'input, F>(folder: &mut F, path: &Path<'input>) -> Path<'input>
+    where F: Folder<'input> + ?Sized
+{
+    match *path {
+        Path::CrateRoot { ref id } => {
+            Path::CrateRoot {
+                id: folder.fold_ident(id),
+            }
+        }
+        Path::InherentImpl { ref impl_path, ref self_type } => {
+            Path::InherentImpl {
+                impl_path: folder.fold_impl_path(impl_path),
+                self_type: folder.fold_type(self_type),
+            }
+        }
+        Path::TraitImpl { ref impl_path, ref self_type, ref trait_name } => {
+            Path::TraitImpl {
+                impl_path: folder.fold_impl_path(impl_path),
+                self_type: folder.fold_type(self_type),
+                trait_name: folder.fold_shared_path(trait_name),
+            }
+        }
+        Path::TraitDef { ref self_type, ref trait_name } => {
+            Path::TraitDef {
+                self_type: folder.fold_type(self_type),
+                trait_name: folder.fold_shared_path(trait_name),
+            }
+        }
+        Path::Nested { ref ns, ref inner, ref ident } => {
+            Path::Nested {
+                ns: ns.clone(),
+                inner: folder.fold_shared_path(inner),
+                ident: ident.as_ref().map(|ident| folder.fold_ident(ident)),
+            }
+        }
+        Path::Generic { ref inner, ref args } => {
+            Path::Generic {
+                inner: folder.fold_shared_path(inner),
+                args: args.iter().map(|arg| folder.fold_generic_arg(arg)).collect(),
+            }
+        }
+        Path::Invalid => Path::Invalid,
+    }
+}
+
+pub fn walk_impl_path<'input, F>(folder: &mut F, impl_path: &ImplPath<'input>) -> ImplPath<'input>
+    where F: Folder<'input> + ?Sized
+{
+    ImplPath {
+        dis: impl_path.dis,
+        path: folder.fold_shared_path(&impl_path.path),
+    }
+}
+
+pub fn walk_generic_arg<'input, F>(folder: &mut F, arg: &GenericArg<'input>) -> GenericArg<'input>
+    where F: Folder<'input> + ?Sized
+{
+    match *arg {
+        GenericArg::Lifetime(ref lt) => GenericArg::Lifetime(folder.fold_lifetime(lt)),
+        GenericArg::Type(ref ty) => GenericArg::Type(folder.fold_type(ty)),
+        GenericArg::Const(ref k) => GenericArg::Const(folder.fold_const(k)),
+    }
+}
+
+pub fn walk_type<'input, F>(folder: &mut F, ty: &Type<'input>) -> Type<'input>
+    where F: Folder<'input> + ?Sized
+{
+    match *ty {
+        Type::BasicType(basic_type) => Type::BasicType(basic_type),
+        Type::Array(ref inner, ref len) => {
+            Type::Array(folder.fold_shared_type(inner), folder.fold_shared_const(len))
+        }
+        Type::Slice(ref inner) => Type::Slice(folder.fold_shared_type(inner)),
+        Type::Named(ref path) => Type::Named(folder.fold_shared_path(path)),
+        Type::Tuple(ref components) => {
+            Type::Tuple(components.iter().map(|ty| folder.fold_type(ty)).collect())
+        }
+        Type::Ref(ref lt, ref inner) => {
+            Type::Ref(lt.as_ref().map(|lt| folder.fold_lifetime(lt)),
+                      folder.fold_shared_type(inner))
+        }
+        Type::RefMut(ref lt, ref inner) => {
+            Type::RefMut(lt.as_ref().map(|lt| folder.fold_lifetime(lt)),
+                         folder.fold_shared_type(inner))
+        }
+        Type::RawPtrConst(ref inner) => Type::RawPtrConst(folder.fold_shared_type(inner)),
+        Type::RawPtrMut(ref inner) => Type::RawPtrMut(folder.fold_shared_type(inner)),
+        Type::Fn(ref fn_sig) => Type::Fn(folder.fold_shared_fn_sig(fn_sig)),
+        Type::DynTrait(ref bounds, ref lt) => {
+            Type::DynTrait(folder.fold_shared_dyn_bounds(bounds), folder.fold_lifetime(lt))
+        }
+        Type::Invalid => Type::Invalid,
+    }
+}
+
+pub fn walk_fn_sig<'input, F>(folder: &mut F, fn_sig: &FnSig<'input>) -> FnSig<'input>
+    where F: Folder<'input> + ?Sized
+{
+    FnSig {
+        binder: folder.fold_binder(&fn_sig.binder),
+        is_unsafe: fn_sig.is_unsafe,
+        abi: fn_sig.abi.clone(),
+        param_types: fn_sig.param_types.iter().map(|ty| folder.fold_type(ty)).collect(),
+        return_type: folder.fold_type(&fn_sig.return_type),
+    }
+}
+
+pub fn walk_dyn_bounds<'input, F>(folder: &mut F, bounds: &DynBounds<'input>) -> DynBounds<'input>
+    where F: Folder<'input> + ?Sized
+{
+    DynBounds {
+        binder: folder.fold_binder(&bounds.binder),
+        traits: bounds.traits.iter().map(|tr| folder.fold_dyn_trait(tr)).collect(),
+    }
+}
+
+pub fn walk_dyn_trait<'input, F>(folder: &mut F, tr: &DynTrait<'input>) -> DynTrait<'input>
+    where F: Folder<'input> + ?Sized
+{
+    DynTrait {
+        path: folder.fold_path(&tr.path),
+        assoc_type_bindings: tr.assoc_type_bindings.iter().map(|binding| {
+            DynTraitAssocBinding {
+                ident: binding.ident.clone(),
+                ty: folder.fold_type(&binding.ty),
+            }
+        }).collect(),
+    }
+}
+
+pub fn walk_const<'input, F>(folder: &mut F, k: &Const<'input>) -> Const<'input>
+    where F: Folder<'input> + ?Sized
+{
+    match *k {
+        Const::Value(ref ty, value) => Const::Value(folder.fold_type(ty), value),
+        Const::Placeholder(ref ty) => Const::Placeholder(folder.fold_type(ty)),
+        Const::Invalid => Const::Invalid,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast_demangle::AstDemangle;
+    use parse::parse;
+
+    struct Identity;
+
+    impl<'input> Folder<'input> for Identity {}
+
+    /// Replaces every `u8` with `char`.
+    struct Substitute;
+
+    impl<'input> Folder<'input> for Substitute {
+        fn fold_type(&mut self, ty: &Type<'input>) -> Type<'input> {
+            match *ty {
+                Type::BasicType(BasicType::U8) => Type::BasicType(BasicType::Char),
+                _ => walk_type(self, ty),
+            }
+        }
+    }
+
+    /// Counts how many crate roots are folded.
+    #[derive(Default)]
+    struct Memoized<'input> {
+        shared: SharedFolds<'input>,
+        folded: usize,
+    }
+
+    impl<'input> Folder<'input> for Memoized<'input> {
+        fn fold_path(&mut self, path: &Path<'input>) -> Path<'input> {
+            if let Path::CrateRoot { .. } = *path {
+                self.folded += 1;
+            }
+            walk_path(self, path)
+        }
+
+        fn shared_folds(&mut self) -> Option<&mut SharedFolds<'input>> {
+            Some(&mut self.shared)
+        }
+    }
+
+    #[test]
+    fn the_default_folder_copies_the_symbol() {
+        for mangled in ::test_data::valid_symbols() {
+            let symbol = parse(mangled.as_bytes()).unwrap();
+
+            assert_eq!(Identity.fold_symbol(&symbol), symbol);
+        }
+    }
+
+    #[test]
+    fn types_can_be_substituted() {
+        // std::Foo<[fn(&u8); 3], dyn std::Iterator<Item=u8>>
+        let symbol = parse(b"_RINxC3std3FooAFG0_RL0_hEuj3_DG_NtB2_8Iteratorp4ItemhEL_E")
+            .unwrap();
+
+        assert_eq!(Substitute.fold_symbol(&symbol).demangle(),
+                   "std::Foo<[fn(&char); 3],std::Iterator<Item=char>>");
+    }
+
+    #[test]
+    fn shared_subtrees_can_be_folded_once() {
+        // std::foo<std::Bar, &std::Bar, &std::Bar>
+        let symbol = parse(b"_RINxC3std3fooNtB2_3BarRBb_RBb_E").unwrap();
+        let mut folder = Memoized::default();
+        let folded = folder.fold_symbol(&symbol);
+
+        let args = match folded.path {
+            Path::Generic { ref args, .. } => args,
+            _ => unreachable!(),
+        };
+
+        match (&args[1], &args[2]) {
+            (&GenericArg::Type(Type::Ref(_, ref first)),
+             &GenericArg::Type(Type::Ref(_, ref second))) => {
+                assert!(Arc::ptr_eq(first, second));
+            }
+            _ => unreachable!(),
+        }
+
+        assert_eq!(folder.folded, 1);
+        assert_eq!(folded, symbol);
+    }
+}
@@ -0,0 +1,414 @@
+//! Generates random, syntactically valid symbols, e.g. as a corpus for
+//! testing other demanglers against this one.
+//!
+//! Generated ASTs can always be mangled and parsed back into the same AST:
+//! identifiers start with an ASCII letter, impl path disambiguators are
+//! non-zero, constants have basic types and there is no encoding version.
+//! Subtrees are reused within a symbol, so that mangling it produces
+//! back-references.
+
+use ast::*;
+use ast_demangle::AstDemangle;
+use ast_mangle::mangle;
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// The default maximum nesting depth of generated paths and types.
+pub const DEFAULT_MAX_DEPTH: usize = 4;
+
+const BASIC_TYPES: &[BasicType] = &[
+    BasicType::Bool, BasicType::Char, BasicType::Str, BasicType::Unit,
+    BasicType::I8, BasicType::I16, BasicType::I32, BasicType::I64,
+    BasicType::I128, BasicType::Isize, BasicType::U8, BasicType::U16,
+    BasicType::U32, BasicType::U64, BasicType::U128, BasicType::Usize,
+    BasicType::F32, BasicType::F64, BasicType::Never, BasicType::Ellipsis,
+    BasicType::Placeholder,
+];
+
+const IDENT_START: &[char] = &['a', 'b', 'f', 'o', 'x', 'z', 'A', 'F', 'Z'];
+
+const IDENT_CHARS: &[char] = &['a', 'e', 'o', 'r', 's', 'B', 'J', 'K', 'T',
+                               '0', '1', '9', '_', 'ä', 'ß', 'λ', '中'];
+
+/// A source of random numbers for the `Generator`.
+pub trait RandomSource {
+    fn next_u64(&mut self) -> u64;
+}
+
+/// A small, seedable pseudo-random number generator, so that the same seed
+/// always produces the same symbols.
+#[derive(Clone, Debug)]
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> SplitMix64 {
+        SplitMix64(seed)
+    }
+}
+
+impl RandomSource for SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Returns `count` random symbols, each mangled and demangled.
+pub fn corpus(seed: u64, count: usize) -> Vec<(String, String)> {
+    let mut generator = Generator::new(SplitMix64::new(seed));
+
+    (0 .. count).map(|_| {
+        let symbol = generator.symbol();
+        let mangled = mangle(&symbol).expect("generated symbols can be mangled");
+        (mangled, symbol.demangle())
+    }).collect()
+}
+
+/// A symbol with `levels` nested tuples, each of which refers back to the
+/// previous one twice, so that expanding the back-references takes
+/// `2^levels` steps. Demanglers are expected to bound their output for it.
+pub fn nested_backrefs(levels: usize) -> String {
+    backref_chain("_RINtC3std3Foo", "NtB2_3Bar", levels, |backref| format!("TR{0}R{0}E", backref), "E")
+}
+
+/// Appends `first` to `prefix`, then `levels` times what `level` makes of a
+/// back-reference to the start of whatever was appended before, and finally
+/// `suffix`.
+pub(crate) fn backref_chain<F>(prefix: &str, first: &str, levels: usize, level: F, suffix: &str)
+                               -> String
+    where F: Fn(&str) -> String
+{
+    use int_radix::radix;
+
+    let mut mangled = prefix.to_string();
+    let mut prev = mangled.len();
+    mangled.push_str(first);
+
+    for _ in 0 .. levels {
+        let backref = format!("B{}_", radix(62, (prev - 3) as u64));
+        prev = mangled.len();
+        mangled.push_str(&level(&backref));
+    }
+
+    mangled.push_str(suffix);
+    mangled
+}
+
+pub struct Generator<R> {
+    rng: R,
+    max_depth: usize,
+    // Subtrees generated for the current symbol, for reuse
+    paths: Vec<Path<'static>>,
+    types: Vec<Type<'static>>,
+    consts: Vec<Const<'static>>,
+}
+
+impl<R: RandomSource> Generator<R> {
+    pub fn new(rng: R) -> Generator<R> {
+        Generator {
+            rng,
+            max_depth: DEFAULT_MAX_DEPTH,
+            paths: Vec::new(),
+            types: Vec::new(),
+            consts: Vec::new(),
+        }
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Generator<R> {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn symbol(&mut self) -> Symbol<'static> {
+        self.paths.clear();
+        self.types.clear();
+        self.consts.clear();
+
+        let depth = self.depth();
+
+        Symbol {
+            version: None,
+            path: self.gen_path(depth),
+            instantiating_crate: if self.coin() {
+                Some(self.gen_crate_root())
+            } else {
+                None
+            },
+        }
+    }
+
+    /// A random depth up to the maximum.
+    pub(crate) fn depth(&mut self) -> usize {
+        let max_depth = self.max_depth;
+        self.below(max_depth + 1)
+    }
+
+    pub(crate) fn gen_path(&mut self, depth: usize) -> Path<'static> {
+        if depth == 0 {
+            return self.gen_crate_root();
+        }
+
+        if let Some(path) = self.reuse(|g| &g.paths) {
+            return path;
+        }
+
+        let depth = depth - 1;
+
+        let path = match self.below(8) {
+            0 => Path::InherentImpl {
+                impl_path: self.gen_impl_path(depth),
+                self_type: self.gen_type(depth),
+            },
+            1 => Path::TraitImpl {
+                impl_path: self.gen_impl_path(depth),
+                self_type: self.gen_type(depth),
+                trait_name: Arc::new(self.gen_path(depth)),
+            },
+            2 => Path::TraitDef {
+                self_type: self.gen_type(depth),
+                trait_name: Arc::new(self.gen_path(depth)),
+            },
+            3 => Path::Generic {
+                inner: Arc::new(self.gen_path(depth)),
+                args: self.gen_vec(3, |g| g.gen_generic_arg(depth)),
+            },
+            _ => Path::Nested {
+                ns: self.gen_namespace(),
+                inner: Arc::new(self.gen_path(depth)),
+                ident: Some(self.gen_ident()),
+            },
+        };
+
+        self.paths.push(path.clone());
+        path
+    }
+
+    fn gen_crate_root(&mut self) -> Path<'static> {
+        Path::CrateRoot { id: self.gen_ident() }
+    }
+
+    fn gen_impl_path(&mut self, depth: usize) -> ImplPath<'static> {
+        ImplPath {
+            dis: if self.coin() {
+                Some(Base62Number(self.number().saturating_add(1)))
+            } else {
+                None
+            },
+            path: Arc::new(self.gen_path(depth)),
+        }
+    }
+
+    fn gen_generic_arg(&mut self, depth: usize) -> GenericArg<'static> {
+        match self.below(4) {
+            0 => GenericArg::Lifetime(self.gen_lifetime()),
+            1 => GenericArg::Const(self.gen_const()),
+            _ => GenericArg::Type(self.gen_type(depth)),
+        }
+    }
+
+    pub(crate) fn gen_type(&mut self, depth: usize) -> Type<'static> {
+        if depth == 0 {
+            return self.gen_basic_type();
+        }
+
+        if let Some(ty) = self.reuse(|g| &g.types) {
+            return ty;
+        }
+
+        let depth = depth - 1;
+
+        let ty = match self.below(12) {
+            0 => Type::Array(Arc::new(self.gen_type(depth)), Arc::new(self.gen_const())),
+            1 => Type::Slice(Arc::new(self.gen_type(depth))),
+            2 | 3 => Type::Named(Arc::new(self.gen_path(depth))),
+            4 => Type::Tuple(self.gen_vec(3, |g| g.gen_type(depth))),
+            5 => Type::Ref(self.gen_optional_lifetime(), Arc::new(self.gen_type(depth))),
+            6 => Type::RefMut(self.gen_optional_lifetime(), Arc::new(self.gen_type(depth))),
+            7 => Type::RawPtrConst(Arc::new(self.gen_type(depth))),
+            8 => Type::RawPtrMut(Arc::new(self.gen_type(depth))),
+            9 => Type::Fn(Arc::new(self.gen_fn_sig(depth))),
+            10 => Type::DynTrait(Arc::new(self.gen_dyn_bounds(depth)), self.gen_lifetime()),
+            _ => return self.gen_basic_type(),
+        };
+
+        self.types.push(ty.clone());
+        ty
+    }
+
+    fn gen_basic_type(&mut self) -> Type<'static> {
+        Type::BasicType(BASIC_TYPES[self.below(BASIC_TYPES.len())])
+    }
+
+    pub(crate) fn gen_fn_sig(&mut self, depth: usize) -> FnSig<'static> {
+        FnSig {
+            binder: self.gen_binder(),
+            is_unsafe: self.coin(),
+            abi: match self.below(3) {
+                0 => None,
+                1 => Some(Abi::C),
+                _ => Some(Abi::Named(self.gen_uident())),
+            },
+            param_types: self.gen_vec(3, |g| g.gen_type(depth)),
+            return_type: self.gen_type(depth),
+        }
+    }
+
+    pub(crate) fn gen_dyn_bounds(&mut self, depth: usize) -> DynBounds<'static> {
+        DynBounds {
+            binder: self.gen_binder(),
+            traits: self.gen_vec(2, |g| DynTrait {
+                path: g.gen_path(depth),
+                assoc_type_bindings: g.gen_vec(2, |g| DynTraitAssocBinding {
+                    ident: g.gen_uident(),
+                    ty: g.gen_type(depth),
+                }),
+            }),
+        }
+    }
+
+    /// Constants always have a basic type, like const generic arguments.
+    pub(crate) fn gen_const(&mut self) -> Const<'static> {
+        if let Some(k) = self.reuse(|g| &g.consts) {
+            return k;
+        }
+
+        let ty = self.gen_basic_type();
+
+        let k = if self.below(4) == 0 {
+            Const::Placeholder(ty)
+        } else {
+            Const::Value(ty, self.number())
+        };
+
+        self.consts.push(k.clone());
+        k
+    }
+
+    fn gen_optional_lifetime(&mut self) -> Option<Lifetime> {
+        if self.coin() {
+            Some(self.gen_lifetime())
+        } else {
+            None
+        }
+    }
+
+    fn gen_lifetime(&mut self) -> Lifetime {
+        Lifetime { debruijn_index: Base62Number(self.number()) }
+    }
+
+    fn gen_binder(&mut self) -> Binder {
+        Binder { count: Base62Number(self.number()) }
+    }
+
+    fn gen_namespace(&mut self) -> Namespace {
+        let c = self.below(26) as u8;
+        Namespace(if self.coin() { b'a' + c } else { b'A' + c })
+    }
+
+    pub(crate) fn gen_ident(&mut self) -> Ident<'static> {
+        Ident {
+            dis: Base62Number(if self.coin() { self.number() } else { 0 }),
+            u_ident: self.gen_uident(),
+        }
+    }
+
+    /// Identifiers start with an ASCII letter, since the mangled form cannot
+    /// express a leading digit without a separator.
+    fn gen_uident(&mut self) -> UIdent<'static> {
+        let mut ident = String::new();
+        ident.push(IDENT_START[self.below(IDENT_START.len())]);
+
+        for _ in 0 .. self.below(8) {
+            ident.push(IDENT_CHARS[self.below(IDENT_CHARS.len())]);
+        }
+
+        UIdent(Cow::Owned(ident))
+    }
+
+    fn gen_vec<T, F: FnMut(&mut Self) -> T>(&mut self, max_len: usize, mut f: F) -> Vec<T> {
+        let len = self.below(max_len + 1);
+        (0 .. len).map(|_| f(self)).collect()
+    }
+
+    /// Sometimes picks one of the subtrees generated so far.
+    fn reuse<T, F>(&mut self, subtrees: F) -> Option<T>
+        where T: Clone,
+              F: Fn(&Self) -> &Vec<T>
+    {
+        let len = subtrees(self).len();
+
+        if len == 0 || self.below(6) != 0 {
+            return None;
+        }
+
+        let index = self.below(len);
+        Some(subtrees(self)[index].clone())
+    }
+
+    /// Mostly small numbers, but occasionally very large ones.
+    fn number(&mut self) -> u64 {
+        match self.below(8) {
+            0 => self.rng.next_u64(),
+            1 => self.below(1000) as u64,
+            _ => self.below(10) as u64,
+        }
+    }
+
+    fn coin(&mut self) -> bool {
+        self.below(2) == 0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.rng.next_u64() % n as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse::{parse, parse_with_spans, Production};
+    use stream_demangle::demangle;
+
+    #[test]
+    fn same_seed_same_corpus() {
+        assert_eq!(corpus(7, 50), corpus(7, 50));
+        assert!(corpus(7, 50) != corpus(8, 50));
+    }
+
+    #[test]
+    fn corpus_parses_and_demangles_consistently() {
+        for (mangled, demangled) in corpus(0, 2000) {
+            assert_eq!(demangled, parse(mangled.as_bytes()).unwrap().demangle(), "{}", mangled);
+
+            let mut streamed = String::new();
+            demangle(mangled.as_bytes(), &mut streamed).unwrap();
+            assert_eq!(demangled, streamed, "{}", mangled);
+        }
+    }
+
+    #[test]
+    fn corpus_covers_the_grammar() {
+        let mut productions = Vec::new();
+        let mut punycode = false;
+
+        for (mangled, demangled) in corpus(0, 2000) {
+            let (_, spans) = parse_with_spans(mangled.as_bytes()).unwrap();
+            productions.extend(spans.iter().map(|span| span.production));
+            punycode |= !demangled.is_ascii();
+        }
+
+        for production in &[Production::Backref, Production::DynBounds,
+                            Production::DynTraitAssocBinding, Production::FnSig,
+                            Production::Abi, Production::Const, Production::ImplPath,
+                            Production::Lifetime, Production::Disambiguator] {
+            assert!(productions.contains(production), "{}", production.name());
+        }
+
+        assert!(punycode);
+    }
+}
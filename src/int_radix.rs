@@ -1,4 +1,6 @@
-use std::fmt;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
 
 pub struct RadixFmt {
     radix: u8,
@@ -15,7 +17,7 @@ impl fmt::Display for RadixFmt {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let RadixFmt { radix, mut value } = *self;
 
-        assert!(radix >= 2 && radix <= 62);
+        assert!((2..=62).contains(&radix));
 
         if value == 0 {
             write!(f, "0")?;
@@ -26,7 +28,7 @@ impl fmt::Display for RadixFmt {
 
         while value > 0 {
             let digit = value % radix;
-            value = value / radix;
+            value /= radix;
             text.push(DIGITS[digit as usize]);
         }
 
@@ -54,17 +56,16 @@ pub fn ascii_digit_to_value(ascii_char: u8, radix: u8) -> Option<u64> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::str;
-    use std::u64;
+    use core::str;
 
     #[test]
     fn ascii_digit_to_value_cross_check() {
-        for i in 0..DIGITS.len() {
+        for (i, &digit) in DIGITS.iter().enumerate() {
             for radix in 0..DIGITS.len() {
                 if i < radix {
-                    assert_eq!(Some(i as u64), ascii_digit_to_value(DIGITS[i], radix as u8));
+                    assert_eq!(Some(i as u64), ascii_digit_to_value(digit, radix as u8));
                 } else {
-                    assert_eq!(None, ascii_digit_to_value(DIGITS[i], radix as u8));
+                    assert_eq!(None, ascii_digit_to_value(digit, radix as u8));
                 }
             }
         }
@@ -89,7 +90,7 @@ mod tests {
 
     quickcheck! {
         fn radix_fmt_vs_std(value: u64, base: u8) -> bool {
-            if base < 2 || base > 36 {
+            if !(2..=36).contains(&base) {
                 return true
             }
 
@@ -0,0 +1,186 @@
+//! Classifying symbols by what kind of item they are for, e.g. to group or
+//! filter the frames of a profile.
+
+use ast::*;
+use visit::{walk_path, SharedNodes, Visitor};
+
+/// What kind of item a symbol is for, and whether it is an instance of a
+/// generic item.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct SymbolKind {
+    pub item: ItemKind,
+    /// Whether any path in the symbol has generic arguments.
+    pub generic: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum ItemKind {
+    /// A free function or a static, e.g. `std::mem::swap`.
+    Item,
+    /// A method in an inherent impl, e.g. `<std::vec::Vec<u8>>::push`.
+    InherentMethod,
+    /// A method in a trait impl, e.g. `<u8 as core::clone::Clone>::clone`.
+    TraitMethod,
+    /// A default method of a trait that the impl does not override, i.e. a
+    /// method of a `Path::TraitDef`.
+    TraitDefaultMethod,
+    /// A closure, i.e. a path in the closure namespace `C`.
+    Closure,
+    /// A shim generated by the compiler, i.e. a path in the shim namespace
+    /// `S`, other than a vtable shim.
+    Shim,
+    /// A shim for calling a method through a vtable.
+    VtableShim,
+    /// The drop glue of a type, i.e. `core::ptr::drop_in_place<T>`.
+    DropGlue,
+}
+
+impl<'input> Symbol<'input> {
+    /// Classifies the symbol by its path. The innermost part of the path
+    /// decides, so a static inside a method is an `ItemKind::Item` and a
+    /// closure inside a method is an `ItemKind::Closure`.
+    pub fn kind(&self) -> SymbolKind {
+        let mut generic = HasGenericArgs::default();
+        generic.visit_path(&self.path);
+
+        SymbolKind {
+            item: item_kind(&self.path),
+            generic: generic.found,
+        }
+    }
+}
+
+fn item_kind(path: &Path) -> ItemKind {
+    if is_drop_in_place(path) {
+        return ItemKind::DropGlue;
+    }
+
+    let (ns, inner, ident) = match *without_generic_args(path) {
+        Path::Nested { ref ns, ref inner, ref ident } => (ns.0, inner, ident),
+        _ => return ItemKind::Item,
+    };
+
+    match ns {
+        b'C' => ItemKind::Closure,
+        b'S' if ident.as_ref().is_some_and(|ident| ident.u_ident.0.starts_with("vtable")) => {
+            ItemKind::VtableShim
+        }
+        b'S' => ItemKind::Shim,
+        _ => {
+            match *without_generic_args(inner) {
+                Path::InherentImpl { .. } => ItemKind::InherentMethod,
+                Path::TraitImpl { .. } => ItemKind::TraitMethod,
+                Path::TraitDef { .. } => ItemKind::TraitDefaultMethod,
+                _ => ItemKind::Item,
+            }
+        }
+    }
+}
+
+fn without_generic_args<'a, 'input>(path: &'a Path<'input>) -> &'a Path<'input> {
+    match *path {
+        Path::Generic { ref inner, .. } => without_generic_args(inner),
+        _ => path,
+    }
+}
+
+fn is_drop_in_place(path: &Path) -> bool {
+    let mut path = without_generic_args(path);
+
+    for &name in &["drop_in_place", "ptr"] {
+        path = match *path {
+            Path::Nested { ref inner, ident: Some(ref ident), .. } if ident.u_ident.0 == name => {
+                inner
+            }
+            _ => return false,
+        };
+    }
+
+    match *path {
+        Path::CrateRoot { ref id } => id.u_ident.0 == "core",
+        _ => false,
+    }
+}
+
+#[derive(Default)]
+struct HasGenericArgs {
+    found: bool,
+    shared: SharedNodes,
+}
+
+impl<'input> Visitor<'input> for HasGenericArgs {
+    fn visit_path(&mut self, path: &Path<'input>) {
+        if let Path::Generic { .. } = *path {
+            self.found = true;
+        } else {
+            walk_path(self, path);
+        }
+    }
+
+    fn shared_nodes(&mut self) -> Option<&mut SharedNodes> {
+        Some(&mut self.shared)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse::parse;
+
+    fn kind(mangled: &str) -> (ItemKind, bool) {
+        let kind = parse(mangled.as_bytes()).unwrap().kind();
+        (kind.item, kind.generic)
+    }
+
+    #[test]
+    fn items() {
+        // mycrate::foo
+        assert_eq!(kind("_RNvC7mycrate3foo"), (ItemKind::Item, false));
+        // std::mem::align_of<usize>
+        assert_eq!(kind("_RINvNtC3std3mem8align_ofjE"), (ItemKind::Item, true));
+        // <std::Foo<_>>::quux::BAR
+        assert_eq!(kind("_RNxNxMC3stdINyC3std3FoopE4quux3BAR"), (ItemKind::Item, true));
+    }
+
+    #[test]
+    fn methods() {
+        // <std::fs::File>::close
+        assert_eq!(kind("_RNxMs47_NyC3std2fsNyNyC3std2fs4File5close"),
+                   (ItemKind::InherentMethod, false));
+        // <std::vec::Vec<char>>::push
+        assert_eq!(kind("_RNxMs23_NyC3std3vecINyNyC3std3vec3VeccE4push"),
+                   (ItemKind::InherentMethod, true));
+        // <&str as mycrate::Foo>::foo
+        assert_eq!(kind("_RNxXC7mycrateReNyC7mycrate3Foo3foo"), (ItemKind::TraitMethod, false));
+        // <usize as mycrate::SomeTrait>::foo
+        assert_eq!(kind("_RNvYjNtC7mycrate9SomeTrait3foo"),
+                   (ItemKind::TraitDefaultMethod, false));
+        // <std::string::String>::retain<xxx::predicate>
+        assert_eq!(kind("_RINxMs89_NyC3std6stringNyNyC3std6string6String6retainNxC3xxx9predicateE"),
+                   (ItemKind::InherentMethod, true));
+    }
+
+    #[test]
+    fn compiler_generated_items() {
+        // mycrate::foo::{closure}[0]
+        assert_eq!(kind("_RNCNvC7mycrate3foo0"), (ItemKind::Closure, false));
+        assert_eq!(kind("_RNSNvC7mycrate3foo5reify"), (ItemKind::Shim, false));
+        assert_eq!(kind("_RNSNvMC7mycrateNtB4_3Foo3bar6vtable"), (ItemKind::VtableShim, false));
+        // core::ptr::drop_in_place<mycrate::Foo>
+        assert_eq!(kind("_RINvNtC4core3ptr13drop_in_placeNtC7mycrate3FooE"),
+                   (ItemKind::DropGlue, true));
+        // mycrate::ptr::drop_in_place
+        assert_eq!(kind("_RNvNtC7mycrate3ptr13drop_in_place"), (ItemKind::Item, false));
+    }
+
+    #[test]
+    fn shared_types_are_visited_once() {
+        use generate::backref_chain;
+
+        // <(std::Bar, (&std::Bar, &std::Bar), (&(..), &(..)), ...)>::bar, where
+        // each element refers back to the previous one twice
+        let mangled = backref_chain("_RNvMC7mycrateT", "NtC3std3Bar", 64,
+                                    |backref| format!("TR{0}R{0}E", backref), "E3bar");
+        assert_eq!(kind(&mangled), (ItemKind::InherentMethod, false));
+    }
+}
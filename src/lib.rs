@@ -1,24 +1,58 @@
-extern crate unic_idna_punycode as punycode;
+#![cfg_attr(not(feature = "std"), no_std)]
 
-#[cfg(test)]
 #[macro_use]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate core;
+#[cfg(feature = "std")]
+extern crate unic_idna_punycode as punycode;
+#[cfg(feature = "std")]
+extern crate unic_normal;
+
+#[cfg(any(test, feature = "arbitrary"))]
+#[cfg_attr(test, macro_use)]
 extern crate quickcheck;
 #[cfg(test)]
 extern crate rand;
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
 
 pub mod ast;
+#[cfg(any(test, feature = "arbitrary"))]
+mod arbitrary;
 pub mod ast_demangle;
+pub mod ast_mangle;
+pub mod builder;
+pub mod cst;
+pub mod explain;
+pub mod fold;
+pub mod generate;
+pub mod kind;
 pub mod parse;
+pub mod parse_demangled;
+pub mod rename;
+pub mod source_map;
+pub mod stream_demangle;
+pub mod template;
+pub mod validate;
+pub mod visit;
 
+mod builtin_punycode;
+#[cfg(not(feature = "std"))]
+use builtin_punycode as punycode;
 mod charset;
 mod error;
 pub mod int_radix;
 
+use alloc::string::String;
+
 #[cfg(test)]
 mod generated_tests;
+#[cfg(test)]
+mod test_data;
 
 /// Construct the AST for a mangled symbol name.
-pub fn mangled_symbol_to_ast(mangled_symbol: &str) -> Result<ast::Symbol, String> {
+pub fn mangled_symbol_to_ast(mangled_symbol: &str) -> Result<ast::Symbol<'_>, String> {
     parse::parse(mangled_symbol.as_bytes())
 }
 
@@ -26,3 +60,19 @@ pub fn mangled_symbol_to_ast(mangled_symbol: &str) -> Result<ast::Symbol, String
 pub fn ast_to_demangled_symbol(symbol_ast: &ast::Symbol) -> String {
     ast_demangle::AstDemangle::demangle(symbol_ast)
 }
+
+/// Generates the mangled symbol name for a symbol's AST.
+pub fn ast_to_mangled_symbol(symbol_ast: &ast::Symbol) -> Result<String, ast_mangle::MangleError> {
+    ast_mangle::mangle(symbol_ast)
+}
+
+/// Demangles a symbol name directly into `out`, without constructing its AST.
+pub fn demangle_symbol<W: core::fmt::Write>(mangled_symbol: &str, out: &mut W) -> Result<(), String> {
+    stream_demangle::demangle(mangled_symbol.as_bytes(), out)
+}
+
+/// Demangles a symbol name into `buf` without allocating, returning the
+/// length of the output.
+pub fn demangle_into(mangled_symbol: &[u8], buf: &mut [u8]) -> Result<usize, stream_demangle::DemangleError> {
+    stream_demangle::demangle_into(mangled_symbol, buf)
+}
@@ -2,52 +2,363 @@ use ast::*;
 use charset;
 use error::{self, expected};
 use int_radix::ascii_digit_to_value;
-use std::str;
-use std::sync::Arc;
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::mem;
+use core::ops::Range;
+use core::str;
 
 pub const EOT: u8 = 5; // ASCII "end of transmission"
 
+/// How deeply productions may be nested, counting the nesting through
+/// back-references. This bounds stack usage and rejects back-references to
+/// an enclosing production, which would never terminate.
+pub(crate) const MAX_DEPTH: usize = 500;
 
-pub fn parse(input: &[u8]) -> Result<Symbol, String> {
-    let mut parser = Parser {
-        input,
-        pos: 0,
-    };
+
+pub fn parse(input: &[u8]) -> Result<Symbol<'_>, String> {
+    parse_with_context(input).map_err(|e| e.to_string())
+}
+
+/// Like `parse` but on failure also tells which productions were being
+/// parsed, e.g. for showing a `ParseError::diagnostic`.
+pub fn parse_with_context(input: &[u8]) -> Result<Symbol<'_>, ParseError> {
+    let mut parser = Parser::new(input);
 
     parser.parse_symbol()
-          .map_err(|e| format!("at position {}: {}", parser.pos, e))
+          .map_err(|failure| parser.error(failure))
+}
+
+/// Like `parse` but does not give up at the first error. Paths, types and
+/// constants that cannot be parsed become `Invalid` nodes and identifiers of
+/// nested paths `None`, all of which demangle as `{invalid}`. Parsing resumes
+/// after the byte the error occurred at, and truncated lists of generic
+/// arguments, types or bounds end at the end of the input. The first error
+/// is returned along with the partial symbol.
+pub fn parse_lenient(input: &[u8]) -> (Symbol<'_>, Option<ParseError>) {
+    let mut parser = Parser::new(input);
+    parser.lenient = true;
+
+    let symbol = match parser.parse_symbol() {
+        Ok(symbol) => symbol,
+        Err(failure) => {
+            parser.record_error(failure);
+
+            Symbol {
+                version: None,
+                path: Path::Invalid,
+                instantiating_crate: None,
+            }
+        }
+    };
+
+    (symbol, parser.first_error)
+}
+
+/// Like `parse` but also records the byte range of every production the
+/// parser went through, in the order they were entered.
+pub fn parse_with_spans(input: &[u8]) -> Result<(Symbol<'_>, Vec<Span>), String> {
+    let mut parser = Parser::new(input);
+    parser.spans = Some(Vec::new());
+    parser.expand_backrefs = true;
+
+    let symbol = parser.parse_symbol()
+                       .map_err(|failure| parser.error(failure).to_string())?;
+
+    Ok((symbol, parser.spans.unwrap()))
+}
+
+/// Like `parse_with_spans` but only records the productions that are spelled
+/// out in the input. A `Backref` span is followed by a single span for the
+/// path, type or constant at its target, without the productions inside it,
+/// so the number of spans stays proportional to the length of the input.
+pub fn parse_with_literal_spans(input: &[u8]) -> Result<(Symbol<'_>, Vec<Span>), String> {
+    let mut parser = Parser::new(input);
+    parser.spans = Some(Vec::new());
+
+    let symbol = parser.parse_symbol()
+                       .map_err(|failure| parser.error(failure).to_string())?;
+
+    Ok((symbol, parser.spans.unwrap()))
+}
+
+/// The grammar productions that show up in a `Span`.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum Production {
+    Path,
+    ImplPath,
+    Type,
+    FnSig,
+    Abi,
+    DynBounds,
+    DynTrait,
+    DynTraitAssocBinding,
+    Const,
+    Lifetime,
+    Binder,
+    Ident,
+    UIdent,
+    Namespace,
+    Disambiguator,
+    Backref,
+}
+
+impl Production {
+    /// The name of the production as used in the grammar, e.g. `<path>`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Production::Path => "<path>",
+            Production::ImplPath => "<impl-path>",
+            Production::Type => "<type>",
+            Production::FnSig => "<fn-sig>",
+            Production::Abi => "<abi>",
+            Production::DynBounds => "<dyn-bounds>",
+            Production::DynTrait => "<dyn-trait>",
+            Production::DynTraitAssocBinding => "<dyn-trait-assoc-binding>",
+            Production::Const => "<const>",
+            Production::Lifetime => "<lifetime>",
+            Production::Binder => "<binder>",
+            Production::Ident => "<ident>",
+            Production::UIdent => "<undisambiguated-ident>",
+            Production::Namespace => "<namespace>",
+            Production::Disambiguator => "<disambiguator>",
+            Production::Backref => "<backref>",
+        }
+    }
+}
+
+/// The byte range `start .. end` of the input that a production was parsed
+/// from. `depth` is the number of productions enclosing this one. Productions
+/// that are reached through a back-reference are recorded with the range of
+/// the back-reference's target and are nested inside the `Backref` span.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Span {
+    pub production: Production,
+    pub start: usize,
+    pub end: usize,
+    pub depth: usize,
+}
+
+/// Why and where parsing a symbol failed.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseError {
+    pub kind: ErrorKind,
+    pub position: usize,
+    pub message: String,
+    /// The productions that were being parsed, outermost first, with the
+    /// positions they start at.
+    pub context: Vec<(Production, usize)>,
+}
+
+impl ParseError {
+    /// Renders the error for people: the symbol with a caret under the byte
+    /// the error occurred at, followed by the productions being parsed.
+    pub fn diagnostic(&self, input: &[u8]) -> String {
+        let symbol = String::from_utf8_lossy(input);
+        let column = String::from_utf8_lossy(&input[.. self.position.min(input.len())])
+            .chars()
+            .count();
+
+        let mut diagnostic = format!("error: {}\n  {}\n  {:>width$}\n",
+                                     self.message,
+                                     symbol,
+                                     "^",
+                                     width = column + 1);
+
+        for (i, &(production, start)) in self.context.iter().rev().enumerate() {
+            let relation = if i == 0 { "while parsing" } else { "inside" };
+            writeln!(diagnostic, "  {} {} starting at {}", relation, production.name(), start)
+                .unwrap();
+        }
+
+        diagnostic
+    }
+}
+
+/// What kind of problem a `ParseError` is about. Unlike the messages, these
+/// are stable, so code and tests should tell errors apart by them.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum ErrorKind {
+    /// The input does not start with `_R`.
+    NotRustSymbol,
+    /// The symbol uses an encoding version that is not supported.
+    UnsupportedVersion,
+    /// A byte, or the end of the input, where it cannot occur.
+    UnexpectedByte,
+    /// A number does not fit into 64 bits.
+    NumberTooLarge,
+    /// An identifier extends beyond the end of the input.
+    TruncatedIdent,
+    /// An identifier is not valid UTF-8 or punycode.
+    InvalidIdent,
+    /// A back-reference to its own position or a later one.
+    ForwardBackref,
+    /// A back-reference to a production that encloses it, which would
+    /// never end.
+    BackrefCycle,
+    /// Productions are nested more than `MAX_DEPTH` deep.
+    NestedTooDeeply,
+    /// The input continues after the symbol.
+    TrailingCharacters,
+}
+
+/// An error inside the parser, which becomes a `ParseError` once the
+/// position and context are added.
+#[derive(Debug)]
+pub(crate) struct Failure {
+    kind: ErrorKind,
+    message: String,
+}
+
+impl Failure {
+    fn new(kind: ErrorKind, message: String) -> Failure {
+        Failure { kind, message }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at position {}: {}", self.position, self.message)
+    }
 }
 
 pub struct Parser<'input> {
     input: &'input [u8],
     pos: usize,
+    spans: Option<Vec<Span>>,
+    /// Whether the spans inside the targets of back-references are recorded
+    /// again at every back-reference, see `parse_with_spans`.
+    expand_backrefs: bool,
+    /// Set while parsing a target whose spans are not recorded.
+    skip_spans: bool,
+    /// The productions being parsed and where they start, outermost first.
+    context: Vec<(Production, usize)>,
+    backref_cache: BackrefCache<'input>,
+    /// Whether to recover from errors, see `parse_lenient`.
+    lenient: bool,
+    /// The first error recovered from in lenient mode.
+    first_error: Option<ParseError>,
+    #[cfg(test)]
+    coverage: Option<Coverage>,
+}
+
+/// Counts which parts of the grammar the parser went through, to find out
+/// what a set of test symbols leaves untested.
+#[cfg(test)]
+#[derive(Default, Debug)]
+pub(crate) struct Coverage {
+    /// How often each production was parsed.
+    pub productions: BTreeMap<Production, usize>,
+    /// How often the parser chose an alternative of a production by its
+    /// leading byte, e.g. `(Path, b'Y')`, or `(UIdent, b'u')` for punycode.
+    /// For `<const>` this is the byte after the type.
+    pub tags: BTreeMap<(Production, u8), usize>,
+    /// How often a production was parsed somewhere inside another one,
+    /// including through back-references, e.g. `(DynBounds, Backref)`.
+    pub nested: BTreeMap<(Production, Production), usize>,
+}
+
+/// Like `parse` but adds the parts of the grammar the parser went through to
+/// `coverage`.
+#[cfg(test)]
+pub(crate) fn parse_with_coverage<'input>(input: &'input [u8],
+                                          coverage: &mut Coverage)
+                                          -> Result<Symbol<'input>, String> {
+    let mut parser = Parser::new(input);
+    parser.coverage = Some(::core::mem::take(coverage));
+
+    let result = parser.parse_symbol()
+                       .map_err(|failure| parser.error(failure).to_string());
+
+    *coverage = parser.coverage.take().unwrap();
+    result
+}
+
+/// The paths, types and constants parsed so far, keyed by their offset, so
+/// that the targets of back-references are not parsed again.
+#[derive(Default)]
+struct BackrefCache<'input> {
+    paths: BTreeMap<usize, Shared<Path<'input>>>,
+    types: BTreeMap<usize, Shared<Type<'input>>>,
+    consts: BTreeMap<usize, Shared<Const<'input>>>,
+}
+
+struct Shared<T> {
+    node: Arc<T>,
+    /// Where parsing continued after the node.
+    end: usize,
+    /// The spans recorded while parsing the node, for recording them again
+    /// at back-references to it.
+    spans: Range<usize>,
 }
 
 impl<'input> Parser<'input> {
 
-    fn parse_symbol(&mut self) -> Result<Symbol, String> {
+    pub(crate) fn new(input: &'input [u8]) -> Parser<'input> {
+        Parser {
+            input,
+            pos: 0,
+            spans: None,
+            expand_backrefs: false,
+            skip_spans: false,
+            context: Vec::new(),
+            backref_cache: BackrefCache::default(),
+            lenient: false,
+            first_error: None,
+            #[cfg(test)]
+            coverage: None,
+        }
+    }
+
+    /// Creates a parser that starts parsing at `pos` within `input`.
+    pub(crate) fn at(input: &'input [u8], pos: usize) -> Parser<'input> {
+        Parser {
+            pos,
+            ..Parser::new(input)
+        }
+    }
+
+    fn parse_symbol(&mut self) -> Result<Symbol<'input>, Failure> {
 
-        if &self.input[0 .. 2] != b"_R" {
-            return Err(format!("Not a Rust symbol"));
+        if !self.input.starts_with(b"_R") {
+            return Err(Failure::new(ErrorKind::NotRustSymbol, "Not a Rust symbol".to_string()));
         }
 
         self.pos += 2;
 
         let version = if self.cur().is_ascii_digit() {
-            let encoding_version = self.parse_number(10)? + 1;
-            return error::version_mismatch(encoding_version, 0);
+            let encoding_version = self.parse_number(10)?.saturating_add(1);
+            return error::version_mismatch(encoding_version, 0)
+                .map_err(|message| Failure::new(ErrorKind::UnsupportedVersion, message));
         } else {
             None
         };
 
         let path = self.parse_path()?;
 
-        let instantiating_crate = if self.cur() != EOT {
+        // After recovering from an error, what follows the path most likely
+        // belongs to it rather than to an instantiating crate.
+        let instantiating_crate = if self.cur() != EOT && self.first_error.is_none() {
             Some(self.parse_path()?)
         } else {
             None
         };
 
+        if self.cur() != EOT {
+            let failure = Failure::new(ErrorKind::TrailingCharacters,
+                                       "unexpected trailing characters".to_string());
+
+            if self.lenient {
+                self.record_error(failure);
+            } else {
+                return Err(failure);
+            }
+        }
+
         Ok(Symbol {
             version,
             path,
@@ -55,29 +366,36 @@ impl<'input> Parser<'input> {
         })
     }
 
-    fn parse_const(&mut self) -> Result<Const, String> {
-        if self.cur() == b'B' {
-            let mut parser = self.parse_backref()?;
-            parser.parse_const()
+    pub(crate) fn parse_const(&mut self) -> Result<Const<'input>, Failure> {
+        Ok((*self.parse_const_arc()?).clone())
+    }
+
+    fn parse_const_inner(&mut self) -> Result<Const<'input>, Failure> {
+        let span = self.enter(Production::Const);
+        let ty = self.parse_type()?;
+
+        #[cfg(test)]
+        self.cover_tag(Production::Const);
+
+        let k = if self.try_eat(b'p') {
+            Const::Placeholder(ty)
         } else {
-            let ty = self.parse_type()?;
+            let value = self.parse_number(16)?;
+            self.eat(b'_', "<const-data>")?;
+            Const::Value(ty, value)
+        };
 
-            if self.cur() == b'p' {
-                Ok(Const::Placeholder(ty))
-            } else {
-                let value = self.parse_number(16)?;
-                self.eat(b'_', "<const-data>")?;
-                Ok(Const::Value(ty, value))
-            }
-        }
+        self.exit(span);
+        Ok(k)
     }
 
-    fn parse_generic_arg(&mut self) -> Result<GenericArg, String> {
+    fn parse_generic_arg(&mut self) -> Result<GenericArg<'input>, Failure> {
         Ok(match self.cur() {
             b'L' => {
                 GenericArg::Lifetime(self.parse_lifetime()?)
             }
             b'K' => {
+                self.pos += 1;
                 GenericArg::Const(self.parse_const()?)
             }
             _ => {
@@ -86,31 +404,48 @@ impl<'input> Parser<'input> {
         })
     }
 
-    fn parse_lifetime(&mut self) -> Result<Lifetime, String> {
+    fn parse_lifetime(&mut self) -> Result<Lifetime, Failure> {
+        let span = self.enter(Production::Lifetime);
         self.eat(b'L', "<lifetime>")?;
-        Ok(Lifetime {
+
+        let lifetime = Lifetime {
             debruijn_index: self.parse_base62_number()?,
-        })
+        };
+
+        self.exit(span);
+        Ok(lifetime)
     }
 
-    fn parse_binder(&mut self) -> Result<Binder, String> {
+    fn parse_binder(&mut self) -> Result<Binder, Failure> {
+        let span = self.enter(Production::Binder);
         self.eat(b'G', "<binder>")?;
 
-        Ok(Binder {
+        let binder = Binder {
             count: self.parse_base62_number()?,
-        })
+        };
+
+        self.exit(span);
+        Ok(binder)
     }
 
-    fn parse_abi(&mut self) -> Result<Abi, String> {
-        if self.cur() == b'C' {
+    fn parse_abi(&mut self) -> Result<Abi<'input>, Failure> {
+        let span = self.enter(Production::Abi);
+        #[cfg(test)]
+        self.cover_tag(Production::Abi);
+
+        let abi = if self.cur() == b'C' {
             self.pos += 1;
-            Ok(Abi::C)
+            Abi::C
         } else {
-            Ok(Abi::Named(self.parse_uident()?))
-        }
+            Abi::Named(self.parse_uident()?)
+        };
+
+        self.exit(span);
+        Ok(abi)
     }
 
-    fn parse_fn_sig(&mut self) -> Result<FnSig, String> {
+    fn parse_fn_sig(&mut self) -> Result<FnSig<'input>, Failure> {
+        let span = self.enter(Production::FnSig);
         let binder = self.parse_binder()?;
         let is_unsafe = self.try_eat(b'U');
         let abi = if self.try_eat(b'K') {
@@ -121,14 +456,15 @@ impl<'input> Parser<'input> {
 
         let mut param_types = Vec::new();
 
-        while self.cur() != b'E' {
+        while !self.at_list_end() {
             param_types.push(self.parse_type()?);
         }
 
-        self.eat(b'E', "<fn-sig>")?;
+        self.eat_list_end("<fn-sig>")?;
 
         let return_type = self.parse_type()?;
 
+        self.exit(span);
         Ok(FnSig {
             binder,
             is_unsafe,
@@ -138,21 +474,24 @@ impl<'input> Parser<'input> {
         })
     }
 
-    fn parse_dyn_bounds(&mut self) -> Result<DynBounds, String> {
+    fn parse_dyn_bounds(&mut self) -> Result<DynBounds<'input>, Failure> {
+        let span = self.enter(Production::DynBounds);
         let binder = self.parse_binder()?;
         let mut traits = Vec::new();
-        while self.cur() != b'E' {
+        while !self.at_list_end() {
             traits.push(self.parse_dyn_trait()?);
         }
-        self.eat(b'E', "<dyn-trait>")?;
+        self.eat_list_end("<dyn-trait>")?;
 
+        self.exit(span);
         Ok(DynBounds {
             binder,
             traits,
         })
     }
 
-    fn parse_dyn_trait(&mut self) -> Result<DynTrait, String> {
+    fn parse_dyn_trait(&mut self) -> Result<DynTrait<'input>, Failure> {
+        let span = self.enter(Production::DynTrait);
         let path = self.parse_path()?;
 
         let mut assoc_type_bindings = Vec::new();
@@ -160,25 +499,40 @@ impl<'input> Parser<'input> {
             assoc_type_bindings.push(self.parse_dyn_trait_assoc_binding()?);
         }
 
+        self.exit(span);
         Ok(DynTrait {
             path,
             assoc_type_bindings,
         })
     }
 
-    fn parse_dyn_trait_assoc_binding(&mut self) -> Result<DynTraitAssocBinding, String> {
+    fn parse_dyn_trait_assoc_binding(&mut self) -> Result<DynTraitAssocBinding<'input>, Failure> {
+        let span = self.enter(Production::DynTraitAssocBinding);
         self.eat(b'p', "<dyn-trait-assoc-binding>")?;
-        Ok(DynTraitAssocBinding {
+
+        let binding = DynTraitAssocBinding {
             ident: self.parse_uident()?,
             ty: self.parse_type()?,
-        })
+        };
+
+        self.exit(span);
+        Ok(binding)
     }
 
-    fn parse_type(&mut self) -> Result<Type, String> {
+    pub(crate) fn parse_type(&mut self) -> Result<Type<'input>, Failure> {
+        Ok((*self.parse_type_arc()?).clone())
+    }
+
+    fn parse_type_inner(&mut self) -> Result<Type<'input>, Failure> {
+        self.check_depth()?;
+
+        let span = self.enter(Production::Type);
         let tag = self.cur();
+        #[cfg(test)]
+        self.cover_tag(Production::Type);
         self.pos += 1;
 
-        Ok(match tag {
+        let ty = match tag {
             b'a' => Type::BasicType(BasicType::I8),
             b'b' => Type::BasicType(BasicType::Bool),
             b'c' => Type::BasicType(BasicType::Char),
@@ -202,25 +556,25 @@ impl<'input> Parser<'input> {
             b'z' => Type::BasicType(BasicType::Never),
 
             b'A' => {
-                Type::Array(Arc::new(self.parse_type()?), Arc::new(self.parse_const()?))
+                Type::Array(self.parse_type_arc()?, self.parse_const_arc()?)
             }
 
             b'S' => {
-                Type::Slice(Arc::new(self.parse_type()?))
+                Type::Slice(self.parse_type_arc()?)
             }
 
             b'C' | b'M' | b'X' | b'Y' |b'N' | b'I' => {
                 self.pos -= 1;
-                Type::Named(Arc::new(self.parse_path()?))
+                Type::Named(self.parse_path_arc()?)
             }
 
             b'T' => {
                 let mut args = Vec::new();
-                while self.cur() != b'E' {
+                while !self.at_list_end() {
                     args.push(self.parse_type()?);
                 }
 
-                self.eat(b'E', "<type>")?;
+                self.eat_list_end("<type>")?;
 
                 Type::Tuple(args)
             }
@@ -232,7 +586,7 @@ impl<'input> Parser<'input> {
                     None
                 };
 
-                Type::Ref(lifetime, Arc::new(self.parse_type()?))
+                Type::Ref(lifetime, self.parse_type_arc()?)
             }
 
             b'Q' => {
@@ -242,15 +596,15 @@ impl<'input> Parser<'input> {
                     None
                 };
 
-                Type::RefMut(lifetime, Arc::new(self.parse_type()?))
+                Type::RefMut(lifetime, self.parse_type_arc()?)
             }
 
             b'P' => {
-                Type::RawPtrConst(Arc::new(self.parse_type()?))
+                Type::RawPtrConst(self.parse_type_arc()?)
             }
 
             b'O' => {
-                Type::RawPtrMut(Arc::new(self.parse_type()?))
+                Type::RawPtrMut(self.parse_type_arc()?)
             }
 
             b'F' => {
@@ -261,35 +615,49 @@ impl<'input> Parser<'input> {
                 Type::DynTrait(Arc::new(self.parse_dyn_bounds()?), self.parse_lifetime()?)
             }
 
-            b'B' => {
-                let mut parser = self.parse_backref()?;
-                parser.parse_type()?
-            }
-
             c => {
-                return Err(format!("Expected start of <type>, found {} instead.", c as char));
+                self.pos -= 1;
+                return Err(unexpected_byte(format!("Expected start of <type>, found {} instead.",
+                                                   error::char_to_str(c as char))));
             }
-        })
+        };
+
+        self.exit(span);
+        Ok(ty)
     }
 
-    fn parse_impl_path(&mut self) -> Result<ImplPath, String> {
+    fn parse_impl_path(&mut self) -> Result<ImplPath<'input>, Failure> {
+        let span = self.enter(Production::ImplPath);
+
         let dis = if self.cur() == b's' {
             Some(self.parse_disambiguator()?)
         } else {
             None
         };
 
-        Ok(ImplPath {
+        let impl_path = ImplPath {
             dis,
-            path: Arc::new(self.parse_path()?),
-        })
+            path: self.parse_path_arc()?,
+        };
+
+        self.exit(span);
+        Ok(impl_path)
     }
 
-    fn parse_path(&mut self) -> Result<Path, String> {
+    pub(crate) fn parse_path(&mut self) -> Result<Path<'input>, Failure> {
+        Ok((*self.parse_path_arc()?).clone())
+    }
+
+    fn parse_path_inner(&mut self) -> Result<Path<'input>, Failure> {
+        self.check_depth()?;
+
+        let span = self.enter(Production::Path);
         let tag = self.cur();
+        #[cfg(test)]
+        self.cover_tag(Production::Path);
         self.pos += 1;
 
-        Ok(match tag {
+        let path = match tag {
             b'C' => {
                 Path::CrateRoot {
                     id: self.parse_ident()?,
@@ -305,115 +673,140 @@ impl<'input> Parser<'input> {
                 Path::TraitImpl {
                     impl_path: self.parse_impl_path()?,
                     self_type: self.parse_type()?,
-                    trait_name: Arc::new(self.parse_path()?),
+                    trait_name: self.parse_path_arc()?,
                 }
             }
             b'Y' => {
                 Path::TraitDef {
                     self_type: self.parse_type()?,
-                    trait_name: Arc::new(self.parse_path()?),
+                    trait_name: self.parse_path_arc()?,
                 }
             }
             b'N' => {
                 Path::Nested {
                     ns: self.parse_namespace()?,
-                    inner: Arc::new(self.parse_path()?),
-                    ident: self.parse_ident()?,
+                    inner: self.parse_path_arc()?,
+                    ident: self.recovering(None, |p| p.parse_ident().map(Some))?,
                 }
             }
             b'I' => {
-                let inner = self.parse_path()?;
+                let inner = self.parse_path_arc()?;
 
                 let mut args = Vec::new();
-                while self.cur() != b'E' {
+                while !self.at_list_end() {
                     args.push(self.parse_generic_arg()?);
                 }
 
-                self.eat(b'E', "<path>")?;
+                self.eat_list_end("<path>")?;
 
                 Path::Generic {
-                    inner: Arc::new(inner),
+                    inner,
                     args,
                 }
             }
-            b'B' => {
-                let mut parser = self.parse_backref()?;
-                parser.parse_path()?
-            }
             other => {
-                return expected("CMXYNIB", other, "parsing", "<path>");
+                self.pos -= 1;
+                return expected("CMXYNIB", other, "parsing", "<path>").map_err(unexpected_byte);
             }
-        })
+        };
+
+        self.exit(span);
+        Ok(path)
     }
 
-    fn parse_namespace(&mut self) -> Result<Namespace, String> {
+    fn parse_namespace(&mut self) -> Result<Namespace, Failure> {
         let c = self.cur();
 
         match c {
             b'A' ..= b'Z' | b'a' ..= b'z' => {}
-            c => return Err(format!("Invalid namespace character '{}'", c))
+            c => return Err(unexpected_byte(format!("Invalid namespace character '{}'", c)))
         };
 
+        let span = self.enter(Production::Namespace);
         self.pos += 1;
+        self.exit(span);
 
         Ok(Namespace(c))
     }
 
-    fn parse_ident(&mut self) -> Result<Ident, String> {
+    pub(crate) fn parse_ident(&mut self) -> Result<Ident<'input>, Failure> {
+        let span = self.enter(Production::Ident);
+
         let dis = if self.cur() == b's' {
             self.parse_disambiguator()?
         } else {
             Base62Number(0)
         };
 
-        Ok(Ident {
+        let ident = Ident {
             dis,
             u_ident: self.parse_uident()?
-        })
+        };
+
+        self.exit(span);
+        Ok(ident)
     }
 
-    fn parse_disambiguator(&mut self) -> Result<Base62Number, String> {
+    fn parse_disambiguator(&mut self) -> Result<Base62Number, Failure> {
+        let span = self.enter(Production::Disambiguator);
         self.eat(b's', "<disambiguator>")?;
 
-        Ok(Base62Number(self.parse_base62_number()?.0 + 1))
+        let dis = match self.parse_base62_number()?.0.checked_add(1) {
+            Some(dis) => Base62Number(dis),
+            None => return Err(number_too_large()),
+        };
+
+        self.exit(span);
+        Ok(dis)
     }
 
-    fn parse_uident(&mut self) -> Result<UIdent, String> {
+    pub(crate) fn parse_uident(&mut self) -> Result<UIdent<'input>, Failure> {
+        let span = self.enter(Production::UIdent);
+        #[cfg(test)]
+        self.cover_tag(Production::UIdent);
         let punycode = self.try_eat(b'u');
         let DecimalNumber(num_bytes) = self.parse_decimal_number()?;
         let start = self.pos;
-        let end = start + num_bytes as usize;
 
-        if end > self.input.len() {
-            return Err(format!("identifier extend beyond end of input"));
+        if num_bytes > (self.input.len() - start) as u64 {
+            return Err(Failure::new(ErrorKind::TruncatedIdent,
+                                    "identifier extend beyond end of input".to_string()));
         }
 
+        let end = start + num_bytes as usize;
+
         self.pos = end;
 
         let bytes = &self.input[start.. end];
 
         let ident = if punycode {
-            charset::decode_punycode_ident(bytes)?
+            Cow::Owned(charset::decode_punycode_ident(bytes).map_err(|message| {
+                Failure::new(ErrorKind::InvalidIdent, message)
+            })?)
         } else {
-            String::from_utf8(bytes.to_owned()).map_err(|e| {
-                format!("{:?}", e)
-            })?
+            Cow::Borrowed(str::from_utf8(bytes).map_err(|e| {
+                Failure::new(ErrorKind::InvalidIdent, format!("{:?}", e))
+            })?)
         };
 
+        self.exit(span);
         Ok(UIdent(ident))
     }
 
 
-    fn parse_decimal_number(&mut self) -> Result<DecimalNumber, String> {
+    fn parse_decimal_number(&mut self) -> Result<DecimalNumber, Failure> {
         Ok(DecimalNumber(self.parse_number(10)?))
     }
 
-    fn parse_base62_number(&mut self) -> Result<Base62Number, String> {
+    fn parse_base62_number(&mut self) -> Result<Base62Number, Failure> {
 
         let n = if self.cur() == b'_' {
             0
         } else {
-            self.parse_number(62)? + 1
+            match self.parse_number(62)?.checked_add(1) {
+                Some(n) => n,
+                None => return Err(number_too_large()),
+            }
         };
 
         self.eat(b'_', "<base-62-number>")?;
@@ -429,10 +822,10 @@ impl<'input> Parser<'input> {
         }
     }
 
-    #[must_use]
-    fn eat(&mut self, c: u8, noun: &str) -> Result<(), String> {
+    fn eat(&mut self, c: u8, noun: &str) -> Result<(), Failure> {
         if self.cur() != c {
-            return expected(str::from_utf8(&[c]).unwrap(), self.cur(), "parsing", noun);
+            return expected(str::from_utf8(&[c]).unwrap(), self.cur(), "parsing", noun)
+                .map_err(unexpected_byte);
         }
 
         self.pos += 1;
@@ -449,36 +842,493 @@ impl<'input> Parser<'input> {
         }
     }
 
-    #[must_use]
-    fn parse_number(&mut self, radix: u8) -> Result<u64, String> {
+    fn parse_number(&mut self, radix: u8) -> Result<u64, Failure> {
         if ascii_digit_to_value(self.cur(), radix).is_none() {
-            return Err(format!(
-                "expected base-{} digit, found {:?}",
+            return Err(unexpected_byte(format!(
+                "expected base-{} digit, found {}",
                 radix,
-                self.cur() as char
-            ));
+                error::char_to_str(self.cur() as char)
+            )));
         }
 
-        let mut value = 0;
+        let mut value: u64 = 0;
 
         while let Some(digit) = ascii_digit_to_value(self.cur(), radix) {
-            value = value * (radix as u64) + digit;
+            value = match value.checked_mul(radix as u64).and_then(|v| v.checked_add(digit)) {
+                Some(value) => value,
+                None => return Err(number_too_large()),
+            };
             self.pos += 1;
         }
 
         Ok(value)
     }
 
-    #[must_use]
-    fn parse_backref(&mut self) -> Result<Parser<'input>, String> {
+    fn parse_path_arc(&mut self) -> Result<Arc<Path<'input>>, Failure> {
+        self.recovering(Arc::new(Path::Invalid), |p| {
+            p.parse_shared(Production::Path, |c| &mut c.paths, Parser::parse_path_inner)
+        })
+    }
+
+    fn parse_type_arc(&mut self) -> Result<Arc<Type<'input>>, Failure> {
+        self.recovering(Arc::new(Type::Invalid), |p| {
+            p.parse_shared(Production::Type, |c| &mut c.types, Parser::parse_type_inner)
+        })
+    }
+
+    fn parse_const_arc(&mut self) -> Result<Arc<Const<'input>>, Failure> {
+        self.recovering(Arc::new(Const::Invalid), |p| {
+            p.parse_shared(Production::Const, |c| &mut c.consts, Parser::parse_const_inner)
+        })
+    }
+
+    /// Parses a path, type or constant via `parse`, or follows the
+    /// back-reference at the current position to one. Every node is parsed
+    /// only once and remembered by where it starts, so that all
+    /// back-references to it share the same `Arc`.
+    fn parse_shared<T, C, P>(&mut self,
+                             production: Production,
+                             cache: C,
+                             parse: P)
+                             -> Result<Arc<T>, Failure>
+        where C: Copy + for<'c> Fn(&'c mut BackrefCache<'input>) -> &'c mut BTreeMap<usize, Shared<T>>,
+              P: Copy + Fn(&mut Self) -> Result<T, Failure>
+    {
+        if self.cur() == b'B' {
+            return self.parse_backref(|p| p.parse_backref_target(production, cache, parse));
+        }
+
+        let start = self.pos;
+
+        // Only a back-reference can lead back to the start of a production
+        // that is still being parsed.
+        if self.context.contains(&(production, start)) {
+            return Err(Failure::new(ErrorKind::BackrefCycle, "cyclic back-reference".to_string()));
+        }
+
+        if let Some(shared) = cache(&mut self.backref_cache).get(&start) {
+            let (node, end, spans) = (shared.node.clone(), shared.end, shared.spans.clone());
+            self.pos = end;
+            self.replay_spans(spans);
+            return Ok(node);
+        }
+
+        let first_span = self.span_count();
+        let node = Arc::new(parse(self)?);
+
+        let shared = Shared {
+            node: node.clone(),
+            end: self.pos,
+            spans: first_span .. self.span_count(),
+        };
+        cache(&mut self.backref_cache).insert(start, shared);
+
+        Ok(node)
+    }
+
+    /// Parses the node at the target of a back-reference. Unless the spans
+    /// inside back-references are expanded, only a single span for the
+    /// target as a whole is recorded.
+    fn parse_backref_target<T, C, P>(&mut self,
+                                     production: Production,
+                                     cache: C,
+                                     parse: P)
+                                     -> Result<Arc<T>, Failure>
+        where C: Copy + for<'c> Fn(&'c mut BackrefCache<'input>) -> &'c mut BTreeMap<usize, Shared<T>>,
+              P: Copy + Fn(&mut Self) -> Result<T, Failure>
+    {
+        if self.spans.is_none() || self.expand_backrefs {
+            return self.parse_shared(production, cache, parse);
+        }
+
+        let start = self.pos;
+        let skip_spans = mem::replace(&mut self.skip_spans, true);
+        let result = self.parse_shared(production, cache, parse);
+        self.skip_spans = skip_spans;
+        let node = result?;
+
+        let depth = self.context.len();
+        let end = self.pos;
+        self.record_span(Span { production, start, end, depth });
+
+        Ok(node)
+    }
+
+    /// Parses a back-reference and then the production at its target, via
+    /// `parse_target`. Afterwards parsing continues right after the
+    /// back-reference.
+    fn parse_backref<T, F>(&mut self, parse_target: F) -> Result<T, Failure>
+        where F: FnOnce(&mut Self) -> Result<T, Failure>
+    {
+        self.check_depth()?;
+
+        let backref_pos = self.pos;
+        let span = self.enter(Production::Backref);
+        self.eat(b'B', "<backref>")?;
+
         let Base62Number(pos) = self.parse_base62_number()?;
+        let return_pos = self.pos;
 
-        // Account for the `_R` prefix
-        let pos = pos + 2;
+        // Back-references may only refer to earlier positions. The `_R`
+        // prefix is not counted in the encoded position.
+        if pos >= (backref_pos - 2) as u64 {
+            return Err(Failure::new(ErrorKind::ForwardBackref,
+                                    "back-reference does not point backwards".to_string()));
+        }
+
+        if let Some(index) = span {
+            self.spans.as_mut().unwrap()[index].end = return_pos;
+        }
+
+        self.pos = pos as usize + 2;
+
+        let result = parse_target(self)?;
 
-        Ok(Parser {
-            input: self.input,
-            pos: pos as usize,
+        self.pos = return_pos;
+        self.context.pop();
+        Ok(result)
+    }
+
+    /// In lenient mode, replaces a failure to parse a production with
+    /// `invalid` and skips the byte the error occurred at.
+    fn recovering<T, F>(&mut self, invalid: T, parse: F) -> Result<T, Failure>
+        where F: FnOnce(&mut Self) -> Result<T, Failure>
+    {
+        let depth = self.context.len();
+
+        // Symbols that are nested too deeply are given up on as a whole. The
+        // productions that failed are not exited, so the context stays at
+        // `MAX_DEPTH` on the way up.
+        match parse(self) {
+            Err(failure) if self.lenient && self.context.len() < MAX_DEPTH => {
+                self.record_error(failure);
+                self.context.truncate(depth);
+                self.pos = (self.pos + 1).min(self.input.len());
+                Ok(invalid)
+            }
+            result => result,
+        }
+    }
+
+    fn record_error(&mut self, failure: Failure) {
+        if self.first_error.is_none() {
+            self.first_error = Some(self.error(failure));
+        }
+    }
+
+    /// Whether a list of generic arguments, types or bounds ends here. In
+    /// lenient mode a truncated list ends at the end of the input.
+    fn at_list_end(&self) -> bool {
+        self.cur() == b'E' || (self.lenient && self.cur() == EOT)
+    }
+
+    fn eat_list_end(&mut self, noun: &str) -> Result<(), Failure> {
+        match self.eat(b'E', noun) {
+            Err(failure) if self.lenient && self.cur() == EOT => {
+                self.record_error(failure);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    fn error(&self, failure: Failure) -> ParseError {
+        ParseError {
+            kind: failure.kind,
+            position: self.pos,
+            message: failure.message,
+            context: self.context.clone(),
+        }
+    }
+
+    fn check_depth(&self) -> Result<(), Failure> {
+        if self.context.len() >= MAX_DEPTH {
+            return Err(Failure::new(ErrorKind::NestedTooDeeply,
+                                    "symbol is nested too deeply".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Starts recording a span for `production` if span recording is enabled.
+    /// Must be paired with a call to `exit` once the production is parsed.
+    fn enter(&mut self, production: Production) -> Option<usize> {
+        let depth = self.context.len();
+        let start = self.pos;
+
+        #[cfg(test)]
+        self.cover_enter(production);
+
+        self.context.push((production, start));
+        self.record_span(Span {
+            production,
+            start,
+            end: start,
+            depth,
         })
     }
+
+    fn record_span(&mut self, span: Span) -> Option<usize> {
+        if self.skip_spans {
+            return None;
+        }
+
+        self.spans.as_mut().map(|spans| {
+            spans.push(span);
+            spans.len() - 1
+        })
+    }
+
+    fn span_count(&self) -> usize {
+        self.spans.as_ref().map_or(0, Vec::len)
+    }
+
+    /// Records the spans of a node that was parsed before once more, as if
+    /// the node was parsed again at the current depth.
+    fn replay_spans(&mut self, range: Range<usize>) {
+        let depth = self.context.len();
+
+        for index in range.clone() {
+            let span = {
+                let spans = self.spans.as_ref().unwrap();
+                Span {
+                    depth: spans[index].depth - spans[range.start].depth + depth,
+                    ..spans[index].clone()
+                }
+            };
+
+            self.record_span(span);
+        }
+    }
+
+    fn exit(&mut self, span: Option<usize>) {
+        self.context.pop();
+
+        if let Some(index) = span {
+            self.spans.as_mut().unwrap()[index].end = self.pos;
+        }
+    }
+
+    #[cfg(test)]
+    fn cover_enter(&mut self, production: Production) {
+        if let Some(ref mut coverage) = self.coverage {
+            *coverage.productions.entry(production).or_insert(0) += 1;
+
+            let mut enclosing: Vec<_> = self.context.iter().map(|&(outer, _)| outer).collect();
+            enclosing.sort();
+            enclosing.dedup();
+
+            for outer in enclosing {
+                *coverage.nested.entry((outer, production)).or_insert(0) += 1;
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn cover_tag(&mut self, production: Production) {
+        let tag = self.cur();
+
+        if let Some(ref mut coverage) = self.coverage {
+            *coverage.tags.entry((production, tag)).or_insert(0) += 1;
+        }
+    }
+}
+
+fn unexpected_byte(message: String) -> Failure {
+    Failure::new(ErrorKind::UnexpectedByte, message)
+}
+
+fn number_too_large() -> Failure {
+    Failure::new(ErrorKind::NumberTooLarge, "number is too large".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast_demangle::AstDemangle;
+
+    #[test]
+    fn only_punycode_identifiers_are_allocated() {
+        let symbol = parse(b"_RNvC7mycrateu10mnchen_Dya").unwrap();
+
+        match symbol.path {
+            Path::Nested { ref inner, ident: Some(ref ident), .. } => {
+                assert!(matches!(ident.u_ident.0, Cow::Owned(ref s) if s == "münchen"));
+
+                match **inner {
+                    Path::CrateRoot { ref id } => {
+                        assert!(matches!(id.u_ident.0, Cow::Borrowed("mycrate")));
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn errors_know_the_enclosing_productions() {
+        let error = parse_with_context(b"_RINxC3std3FooKjzz_E").unwrap_err();
+
+        assert_eq!(ErrorKind::UnexpectedByte, error.kind);
+        assert_eq!(16, error.position);
+        assert_eq!(vec![(Production::Path, 2), (Production::Const, 15)], error.context);
+        assert_eq!("at position 16: expected base-16 digit, found 'z'", error.to_string());
+    }
+
+    #[test]
+    fn diagnostics_point_at_the_error() {
+        let input = b"_RINxC3std3FooRL0_Xu";
+        let error = parse_with_context(input).unwrap_err();
+
+        assert_eq!("error: Expected 'C', 'M', 'X', 'Y', 'N', 'I', or 'B'; found 'u' instead; \
+                    while parsing <path>\n\
+                    \x20 _RINxC3std3FooRL0_Xu\n\
+                    \x20                    ^\n\
+                    \x20 while parsing <path> starting at 19\n\
+                    \x20 inside <impl-path> starting at 19\n\
+                    \x20 inside <path> starting at 18\n\
+                    \x20 inside <type> starting at 18\n\
+                    \x20 inside <type> starting at 14\n\
+                    \x20 inside <path> starting at 2\n",
+                   error.diagnostic(input));
+    }
+
+    #[test]
+    fn inputs_shorter_than_the_prefix_are_rejected() {
+        assert!(parse(b"").is_err());
+        assert!(parse(b"_").is_err());
+    }
+
+    #[test]
+    fn lenient_parsing_recovers_with_placeholders() {
+        let demangle_lenient = |mangled: &str| {
+            let (symbol, error) = parse_lenient(mangled.as_bytes());
+            (symbol.demangle(), error.map(|e| e.to_string()))
+        };
+
+        assert_eq!(demangle_lenient("_RNvINtNtC3std3vec3VecWE4push"),
+                   ("std::vec::Vec<{invalid}>::push".to_string(),
+                    Some("at position 22: Expected start of <type>, found 'W' instead.".to_string())));
+        assert_eq!(demangle_lenient("_RINtC3std3FooNtC3std3Bar"),
+                   ("std::Foo<std::Bar>".to_string(),
+                    Some("at position 25: Expected 'E'; found end of input instead; while parsing <path>"
+                         .to_string())));
+        assert_eq!(demangle_lenient("_RNvINtNtC3std3vec3VecNtC3std6StringE4pu"),
+                   ("std::vec::Vec<std::String>::{invalid}".to_string(),
+                    Some("at position 38: identifier extend beyond end of input".to_string())));
+        assert_eq!(demangle_lenient("_RINtC3std3FooKjE").0, "std::Foo<{invalid}>");
+        assert_eq!(demangle_lenient("_RIC3fooBV_E").0, "foo<{invalid}>");
+        assert_eq!(demangle_lenient("_RNvB_3foo").0, "{invalid}::{invalid}");
+        assert_eq!(demangle_lenient("_X").0, "{invalid}");
+    }
+
+    #[test]
+    fn lenient_parsing_agrees_with_strict_parsing() {
+        for mangled in ::test_data::mangled_symbols() {
+            let (symbol, error) = parse_lenient(mangled.as_bytes());
+
+            match parse_with_context(mangled.as_bytes()) {
+                Ok(strict) => assert_eq!((symbol, error), (strict, None)),
+                Err(strict) => assert_eq!(error.map(|e| (e.kind, e.position)),
+                                          Some((strict.kind, strict.position))),
+            }
+        }
+    }
+
+    #[test]
+    fn nested_backrefs_are_parsed_once() {
+        let mangled = ::generate::nested_backrefs(64);
+
+        assert!(parse(mangled.as_bytes()).is_ok());
+
+        let (_, spans) = parse_with_literal_spans(mangled.as_bytes()).unwrap();
+        assert!(spans.len() < mangled.len());
+    }
+
+    #[test]
+    fn backrefs_share_the_node_at_their_target() {
+        // std::foo<std::Bar, &std::Bar, &std::Bar>
+        let symbol = parse(b"_RINxC3std3fooNtB2_3BarRBb_RBb_E").unwrap();
+
+        let args = match symbol.path {
+            Path::Generic { ref args, .. } => args,
+            _ => unreachable!(),
+        };
+
+        let referenced: Vec<_> = args[1..].iter().map(|arg| match *arg {
+            GenericArg::Type(Type::Ref(_, ref ty)) => ty.clone(),
+            _ => unreachable!(),
+        }).collect();
+
+        assert!(Arc::ptr_eq(&referenced[0], &referenced[1]));
+
+        // The first back-reference already shares the path of the first
+        // occurrence
+        match (&args[0], &*referenced[0]) {
+            (GenericArg::Type(Type::Named(first)), Type::Named(referenced)) => {
+                assert!(Arc::ptr_eq(first, referenced));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Fails for every part of the grammar that no symbol in the test data
+    /// exercises.
+    #[test]
+    fn test_data_covers_the_grammar() {
+        let mut coverage = Coverage::default();
+
+        for mangled in ::test_data::valid_symbols() {
+            parse_with_coverage(mangled.as_bytes(), &mut coverage).unwrap();
+        }
+
+        let mut missing = Vec::new();
+
+        for &production in &[Production::Path, Production::ImplPath, Production::Type,
+                             Production::FnSig, Production::Abi, Production::DynBounds,
+                             Production::DynTrait, Production::DynTraitAssocBinding,
+                             Production::Const, Production::Lifetime, Production::Binder,
+                             Production::Ident, Production::UIdent, Production::Namespace,
+                             Production::Disambiguator, Production::Backref] {
+            if !coverage.productions.contains_key(&production) {
+                missing.push(production.name().to_string());
+            }
+        }
+
+        let tags = [(Production::Path, "CMXYNI"),
+                    (Production::Type, "abcdefhijlmnopstuvxyzASCMXYNITRQPOFD"),
+                    // Placeholder constants
+                    (Production::Const, "p"),
+                    (Production::Abi, "C"),
+                    // Punycode identifiers
+                    (Production::UIdent, "u")];
+
+        for &(production, tags) in &tags {
+            for tag in tags.bytes() {
+                if !coverage.tags.contains_key(&(production, tag)) {
+                    missing.push(format!("{} starting with '{}'", production.name(), tag as char));
+                }
+            }
+        }
+
+        // Named ABIs, back-references in the places
+        // that can hold them, and lifetimes in types and generic arguments
+        let nested = [(Production::Abi, Production::UIdent),
+                      (Production::DynBounds, Production::Backref),
+                      (Production::FnSig, Production::Backref),
+                      (Production::ImplPath, Production::Backref),
+                      (Production::Type, Production::Lifetime),
+                      (Production::Path, Production::Lifetime),
+                      (Production::ImplPath, Production::Disambiguator)];
+
+        for &(outer, inner) in &nested {
+            if !coverage.nested.contains_key(&(outer, inner)) {
+                missing.push(format!("{} inside {}", inner.name(), outer.name()));
+            }
+        }
+
+        assert!(missing.is_empty(),
+                "not covered by the test data: {:#?}\n{:#?}", missing, coverage);
+    }
 }
@@ -0,0 +1,706 @@
+use ast::*;
+use parse::MAX_DEPTH;
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Parses a demangled path like `<alloc::vec::Vec<u8> as core::ops::Drop>::drop`
+/// into its AST, i.e. the reverse of `AstDemangle`. The path is assumed to
+/// name a value, so its last identifier is put into the value namespace and
+/// all others into the type namespace.
+///
+/// The text does not contain everything the AST does: namespaces other than
+/// the type, value and closure namespaces come out as one of these, trait
+/// impls come out as trait definitions, and the impl path of an inherent
+/// impl is taken to be the parent of its self type (or `core` for self
+/// types without a path, since only the standard library can have these).
+pub fn parse_path(text: &str) -> Result<Path<'_>, String> {
+    let mut parser = TextParser::new(text);
+    let path = parser.parse_path(true).map_err(|e| parser.error(e))?;
+    parser.expect_end()?;
+    Ok(path)
+}
+
+/// Parses a demangled type like `&mut [(i32, fn(&str) -> bool)]`. Integer
+/// constants are taken to be `usize`s, and all lifetimes are erased.
+pub fn parse_type(text: &str) -> Result<Type<'_>, String> {
+    let mut parser = TextParser::new(text);
+    let ty = parser.parse_type().map_err(|e| parser.error(e))?;
+    parser.expect_end()?;
+    Ok(ty)
+}
+
+/// Parses a demangled symbol, i.e. a path optionally followed by
+/// ` @ instantiating_crate`.
+pub fn parse_symbol(text: &str) -> Result<Symbol<'_>, String> {
+    parse_symbol_with_crates(text, &[])
+}
+
+/// Like `parse_symbol`, but gives the crate roots named in `crates` the
+/// disambiguator listed there, unless the text specifies one.
+pub fn parse_symbol_with_crates<'input>(text: &'input str,
+                                        crates: &[(&str, Base62Number)])
+                                        -> Result<Symbol<'input>, String> {
+    let mut parser = TextParser::new(text);
+    parser.crates = crates;
+    let symbol = parser.parse_symbol().map_err(|e| parser.error(e))?;
+    parser.expect_end()?;
+    Ok(symbol)
+}
+
+struct TextParser<'input, 'c> {
+    input: &'input str,
+    pos: usize,
+    crates: &'c [(&'c str, Base62Number)],
+    // How many paths and types are being parsed, bounded by `MAX_DEPTH` like
+    // the nesting of mangled symbols.
+    depth: usize,
+}
+
+impl<'input, 'c> TextParser<'input, 'c> {
+
+    fn new(input: &'input str) -> TextParser<'input, 'c> {
+        TextParser { input, pos: 0, crates: &[], depth: 0 }
+    }
+
+    fn parse_symbol(&mut self) -> Result<Symbol<'input>, String> {
+        let path = self.parse_path(true)?;
+
+        let instantiating_crate = if self.eat("@") {
+            Some(self.parse_path(false)?)
+        } else {
+            None
+        };
+
+        Ok(Symbol {
+            version: None,
+            path,
+            instantiating_crate,
+        })
+    }
+
+    fn parse_path(&mut self, value: bool) -> Result<Path<'input>, String> {
+        self.nested(|parser| parser.parse_path_inner(value))
+    }
+
+    fn parse_path_inner(&mut self, value: bool) -> Result<Path<'input>, String> {
+        let mut path = if self.eat("<") {
+            let self_type = self.parse_type()?;
+
+            if self.eat_keyword("as") {
+                let trait_name = self.parse_path(false)?;
+                self.expect(">")?;
+
+                Path::TraitDef {
+                    self_type,
+                    trait_name: Arc::new(trait_name),
+                }
+            } else {
+                self.expect(">")?;
+
+                Path::InherentImpl {
+                    impl_path: ImplPath {
+                        dis: None,
+                        path: Arc::new(inherent_impl_path(&self_type)),
+                    },
+                    self_type,
+                }
+            }
+        } else {
+            let mut id = self.parse_ident()?;
+
+            if id.dis == Base62Number(0) {
+                if let Some(&(_, dis)) = self.crates.iter().find(|&&(name, _)| name == id.u_ident.0) {
+                    id.dis = dis;
+                }
+            }
+
+            let path = Path::CrateRoot { id };
+            let args = self.parse_generic_args()?;
+            with_generic_args(path, args)
+        };
+
+        while self.eat("::") {
+            if self.peek("<") {
+                let args = self.parse_generic_args()?;
+                path = with_generic_args(path, args);
+                continue;
+            }
+
+            let (ns, ident) = if self.eat("{closure}") {
+                (Namespace(b'C'), Ident {
+                    dis: self.parse_disambiguator()?,
+                    u_ident: UIdent(Cow::Borrowed("")),
+                })
+            } else {
+                (Namespace(b't'), self.parse_ident()?)
+            };
+
+            let args = self.parse_generic_args()?;
+
+            let nested = Path::Nested {
+                ns,
+                inner: Arc::new(path),
+                ident: Some(ident),
+            };
+
+            path = with_generic_args(nested, args);
+        }
+
+        Ok(if value { into_value_path(path) } else { path })
+    }
+
+    /// Parses a generic argument list, if one follows. Lists of associated
+    /// type bindings are left alone.
+    fn parse_generic_args(&mut self) -> Result<Option<Vec<GenericArg<'input>>>, String> {
+        if !self.peek("<") || self.assoc_type_bindings_follow() {
+            return Ok(None);
+        }
+
+        self.expect("<")?;
+
+        let mut args = Vec::new();
+        while !self.eat(">") {
+            if !args.is_empty() {
+                self.expect(",")?;
+            }
+            args.push(self.parse_generic_arg()?);
+        }
+
+        Ok(Some(args))
+    }
+
+    fn parse_generic_arg(&mut self) -> Result<GenericArg<'input>, String> {
+        if self.eat("'") {
+            self.parse_ident_chars()?;
+            return Ok(GenericArg::Lifetime(Lifetime { debruijn_index: Base62Number(0) }));
+        }
+
+        if self.peek_digit() || self.peek("{const") {
+            return Ok(GenericArg::Const(self.parse_const()?));
+        }
+
+        Ok(GenericArg::Type(self.parse_type()?))
+    }
+
+    fn parse_const(&mut self) -> Result<Const<'input>, String> {
+        if self.eat("{const") {
+            let ty = self.parse_type()?;
+            self.expect("}")?;
+            Ok(Const::Placeholder(ty))
+        } else {
+            Ok(Const::Value(Type::BasicType(BasicType::Usize), self.parse_number()?))
+        }
+    }
+
+    fn parse_type(&mut self) -> Result<Type<'input>, String> {
+        self.nested(TextParser::parse_type_inner)
+    }
+
+    fn parse_type_inner(&mut self) -> Result<Type<'input>, String> {
+        if self.eat("&") {
+            if self.eat("'") {
+                self.parse_ident_chars()?;
+            }
+
+            return Ok(if self.eat_keyword("mut") {
+                Type::RefMut(None, Arc::new(self.parse_type()?))
+            } else {
+                Type::Ref(None, Arc::new(self.parse_type()?))
+            });
+        }
+
+        if self.eat("*") {
+            return Ok(if self.eat_keyword("const") {
+                Type::RawPtrConst(Arc::new(self.parse_type()?))
+            } else if self.eat_keyword("mut") {
+                Type::RawPtrMut(Arc::new(self.parse_type()?))
+            } else {
+                return Err("expected `const` or `mut`".to_string());
+            });
+        }
+
+        if self.eat("[") {
+            let inner = Arc::new(self.parse_type()?);
+
+            return Ok(if self.eat(";") {
+                let len = Arc::new(self.parse_const()?);
+                self.expect("]")?;
+                Type::Array(inner, len)
+            } else {
+                self.expect("]")?;
+                Type::Slice(inner)
+            });
+        }
+
+        if self.eat("(") {
+            if self.eat(")") {
+                return Ok(Type::BasicType(BasicType::Unit));
+            }
+
+            let mut components = vec![self.parse_type()?];
+            while self.eat(",") {
+                if self.peek(")") {
+                    break;
+                }
+                components.push(self.parse_type()?);
+            }
+            self.expect(")")?;
+
+            return Ok(Type::Tuple(components));
+        }
+
+        if self.eat("!") {
+            return Ok(Type::BasicType(BasicType::Never));
+        }
+
+        if self.eat("...") {
+            return Ok(Type::BasicType(BasicType::Ellipsis));
+        }
+
+        if self.peek_keyword("unsafe") || self.peek_keyword("extern") || self.peek_keyword("fn") {
+            return Ok(Type::Fn(Arc::new(self.parse_fn_sig()?)));
+        }
+
+        if self.eat_keyword("dyn") {
+            return self.parse_dyn_trait();
+        }
+
+        if let Some(basic_type) = self.try_parse_basic_type() {
+            return Ok(Type::BasicType(basic_type));
+        }
+
+        // A trait object with a single trait and no associated type bindings
+        // demangles to just its path and thus comes out as a named type.
+        let start = self.pos;
+        let path = self.parse_path(false)?;
+
+        if self.peek("+") || self.assoc_type_bindings_follow() {
+            self.pos = start;
+            return self.parse_dyn_trait();
+        }
+
+        Ok(Type::Named(Arc::new(path)))
+    }
+
+    fn try_parse_basic_type(&mut self) -> Option<BasicType> {
+        let start = self.pos;
+        let name = self.parse_ident_chars().ok()?;
+
+        let basic_type = match name {
+            "bool" => BasicType::Bool,
+            "char" => BasicType::Char,
+            "str" => BasicType::Str,
+            "i8" => BasicType::I8,
+            "i16" => BasicType::I16,
+            "i32" => BasicType::I32,
+            "i64" => BasicType::I64,
+            "i128" => BasicType::I128,
+            "isize" => BasicType::Isize,
+            "u8" => BasicType::U8,
+            "u16" => BasicType::U16,
+            "u32" => BasicType::U32,
+            "u64" => BasicType::U64,
+            "u128" => BasicType::U128,
+            "usize" => BasicType::Usize,
+            "f32" => BasicType::F32,
+            "f64" => BasicType::F64,
+            "_" => BasicType::Placeholder,
+            _ => {
+                self.pos = start;
+                return None;
+            }
+        };
+
+        // `str::foo` or `u8[1]` are paths that happen to start like a basic type
+        if self.peek("::") || self.peek("[") {
+            self.pos = start;
+            return None;
+        }
+
+        Some(basic_type)
+    }
+
+    fn parse_fn_sig(&mut self) -> Result<FnSig<'input>, String> {
+        let is_unsafe = self.eat_keyword("unsafe");
+
+        let abi = if self.eat_keyword("extern") {
+            self.expect("\"")?;
+            let start = self.pos;
+            let end = match self.input[start..].find('"') {
+                Some(len) => start + len,
+                None => return Err("unterminated ABI name".to_string()),
+            };
+            self.pos = end + 1;
+
+            Some(match &self.input[start .. end] {
+                "C" => Abi::C,
+                name => Abi::Named(UIdent(Cow::Borrowed(name))),
+            })
+        } else {
+            None
+        };
+
+        if !self.eat_keyword("fn") {
+            return Err("expected `fn`".to_string());
+        }
+        self.expect("(")?;
+
+        let mut param_types = Vec::new();
+        while !self.eat(")") {
+            if !param_types.is_empty() {
+                self.expect(",")?;
+            }
+            param_types.push(self.parse_type()?);
+        }
+
+        let return_type = if self.eat("->") {
+            self.parse_type()?
+        } else {
+            Type::BasicType(BasicType::Unit)
+        };
+
+        Ok(FnSig {
+            binder: Binder { count: Base62Number(0) },
+            is_unsafe,
+            abi,
+            param_types,
+            return_type,
+        })
+    }
+
+    fn parse_dyn_trait(&mut self) -> Result<Type<'input>, String> {
+        let mut traits = Vec::new();
+
+        loop {
+            let path = self.parse_path(false)?;
+            let mut assoc_type_bindings = Vec::new();
+
+            if self.assoc_type_bindings_follow() {
+                self.expect("<")?;
+
+                while !self.eat(">") {
+                    if !assoc_type_bindings.is_empty() {
+                        self.expect(",")?;
+                    }
+
+                    let ident = UIdent(Cow::Borrowed(self.parse_ident_chars()?));
+                    self.expect("=")?;
+                    let ty = self.parse_type()?;
+
+                    assoc_type_bindings.push(DynTraitAssocBinding { ident, ty });
+                }
+            }
+
+            traits.push(DynTrait { path, assoc_type_bindings });
+
+            if !self.eat("+") {
+                break;
+            }
+        }
+
+        Ok(Type::DynTrait(Arc::new(DynBounds {
+            binder: Binder { count: Base62Number(0) },
+            traits,
+        }), Lifetime { debruijn_index: Base62Number(0) }))
+    }
+
+    fn parse_ident(&mut self) -> Result<Ident<'input>, String> {
+        let name = self.parse_ident_chars()?;
+
+        Ok(Ident {
+            dis: self.parse_disambiguator()?,
+            u_ident: UIdent(Cow::Borrowed(name)),
+        })
+    }
+
+    fn parse_disambiguator(&mut self) -> Result<Base62Number, String> {
+        if !self.peek("[") {
+            return Ok(Base62Number(0));
+        }
+
+        self.expect("[")?;
+        let dis = self.parse_number()?;
+        self.expect("]")?;
+
+        Ok(Base62Number(dis))
+    }
+
+    fn parse_ident_chars(&mut self) -> Result<&'input str, String> {
+        self.skip_whitespace();
+
+        let start = self.pos;
+        let len = self.input[start..]
+            .find(|c: char| !is_ident_char(c))
+            .unwrap_or(self.input.len() - start);
+
+        if len == 0 {
+            return Err("expected identifier".to_string());
+        }
+
+        self.pos += len;
+        Ok(&self.input[start .. start + len])
+    }
+
+    fn parse_number(&mut self) -> Result<u64, String> {
+        self.skip_whitespace();
+
+        let start = self.pos;
+        let len = self.input[start..]
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.input.len() - start);
+
+        let number = self.input[start .. start + len]
+            .parse()
+            .map_err(|_| "expected number".to_string())?;
+
+        self.pos += len;
+        Ok(number)
+    }
+
+    fn assoc_type_bindings_follow(&self) -> bool {
+        let mut lookahead = TextParser::new(self.input);
+        lookahead.pos = self.pos;
+
+        lookahead.eat("<") &&
+            lookahead.parse_ident_chars().is_ok() &&
+            lookahead.peek("=")
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self, s: &str) -> bool {
+        self.skip_whitespace();
+        self.input[self.pos..].starts_with(s)
+    }
+
+    fn peek_digit(&mut self) -> bool {
+        self.skip_whitespace();
+        self.input[self.pos..].starts_with(|c: char| c.is_ascii_digit())
+    }
+
+    fn peek_keyword(&mut self, keyword: &str) -> bool {
+        self.peek(keyword) &&
+            !self.input[self.pos + keyword.len()..].starts_with(is_ident_char)
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        if self.peek(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek_keyword(keyword) {
+            self.pos += keyword.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), String> {
+        if self.eat(s) {
+            Ok(())
+        } else {
+            Err(format!("expected `{}`", s))
+        }
+    }
+
+    fn nested<T, F>(&mut self, parse: F) -> Result<T, String>
+        where F: FnOnce(&mut Self) -> Result<T, String>
+    {
+        if self.depth >= MAX_DEPTH {
+            return Err("text is nested too deeply".to_string());
+        }
+
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn expect_end(&mut self) -> Result<(), String> {
+        self.skip_whitespace();
+
+        if self.pos == self.input.len() {
+            Ok(())
+        } else {
+            Err(self.error("unexpected trailing characters".to_string()))
+        }
+    }
+
+    fn error(&self, message: String) -> String {
+        format!("at position {}: {}", self.pos, message)
+    }
+}
+
+fn with_generic_args<'input>(path: Path<'input>, args: Option<Vec<GenericArg<'input>>>) -> Path<'input> {
+    match args {
+        Some(args) => Path::Generic {
+            inner: Arc::new(path),
+            args,
+        },
+        None => path,
+    }
+}
+
+/// Puts the last identifier of `path` into the value namespace.
+fn into_value_path(path: Path<'_>) -> Path<'_> {
+    match path {
+        Path::Nested { ns: Namespace(b't'), inner, ident } => Path::Nested {
+            ns: Namespace(b'v'),
+            inner,
+            ident,
+        },
+        Path::Generic { inner, args } => Path::Generic {
+            inner: Arc::new(into_value_path((*inner).clone())),
+            args,
+        },
+        path => path,
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn inherent_impl_path<'input>(self_type: &Type<'input>) -> Path<'input> {
+    let mut path = match *self_type {
+        Type::Named(ref path) => (**path).clone(),
+        _ => {
+            return Path::CrateRoot {
+                id: Ident {
+                    dis: Base62Number(0),
+                    u_ident: UIdent(Cow::Borrowed("core")),
+                },
+            };
+        }
+    };
+
+    while let Path::Generic { inner, .. } = path {
+        path = (*inner).clone();
+    }
+
+    match path {
+        Path::Nested { inner, .. } => (*inner).clone(),
+        path => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast_demangle::AstDemangle;
+    use builder::{Ns, SymbolBuilder};
+    use parse::parse;
+
+    #[test]
+    fn round_trips_test_data() {
+        for mangled in ::test_data::valid_symbols() {
+            let demangled = parse(mangled.as_bytes()).unwrap().demangle();
+
+            let symbol = parse_symbol(&demangled)
+                .unwrap_or_else(|e| panic!("{}: {}", demangled, e));
+
+            assert_eq!(demangled, symbol.demangle());
+        }
+    }
+
+    #[test]
+    fn same_ast_as_parsed_symbol() {
+        for mangled in &["_RNvINtC3std3VechE4push",
+                         "_RNvNtC7mycrate3foo3bar",
+                         "_RINvC7mycrate3bazRNtC3std3StrE"] {
+            let symbol = parse(mangled.as_bytes()).unwrap();
+            assert_eq!(Ok(symbol.path.clone()), parse_path(&symbol.demangle()), "{}", mangled);
+        }
+    }
+
+    #[test]
+    fn trait_impl_method() {
+        let drop = SymbolBuilder::crate_root("core")
+            .child(Ns::Type, "ops")
+            .child(Ns::Type, "Drop")
+            .path();
+        let vec = SymbolBuilder::crate_root("alloc")
+            .child(Ns::Type, "vec")
+            .child(Ns::Type, "Vec")
+            .generic(vec![Type::u8()])
+            .path();
+
+        let expected = SymbolBuilder::trait_def(Type::named(vec), drop)
+            .child(Ns::Value, "drop")
+            .path();
+
+        assert_eq!(Ok(expected), parse_path("<alloc::vec::Vec<u8> as core::ops::Drop>::drop"));
+    }
+
+    #[test]
+    fn types() {
+        let expected = Type::reference_mut(Type::slice(Type::tuple(vec![
+            Type::i32(),
+            Type::fn_ptr(FnSig::new(vec![Type::reference(Type::str())], Type::bool())),
+        ])));
+
+        assert_eq!(Ok(expected), parse_type("&mut [(i32, fn(&str) -> bool)]"));
+
+        for text in &["[u8; 16]",
+                      "*const *mut !",
+                      "unsafe extern \"C\" fn(...)",
+                      "std::Iterator<Item=u8>+std::Send",
+                      "std::Foo<'_,{const bool},3>"] {
+            let ty = parse_type(text).unwrap_or_else(|e| panic!("{}: {}", text, e));
+            assert_eq!(text.replace(", ", ","), ty.demangle().replace(", ", ","));
+        }
+    }
+
+    #[test]
+    fn inherent_impl_paths() {
+        let path = parse_path("<std::vec::Vec<char>>::push").unwrap();
+
+        match path {
+            Path::Nested { ref inner, .. } => match **inner {
+                Path::InherentImpl { ref impl_path, .. } => {
+                    assert_eq!("std::vec", impl_path.path.demangle());
+                }
+                ref other => panic!("unexpected {:?}", other),
+            },
+            ref other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn crate_disambiguators() {
+        let crates = [("std", Base62Number(5)), ("other", Base62Number(7))];
+        let symbol = parse_symbol_with_crates("std::foo<other::Bar, mine::Baz> @ other[3]", &crates)
+            .unwrap();
+
+        assert_eq!("std[5]::foo<other[7]::Bar,mine::Baz> @ other[3]", symbol.demangle());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Err("at position 8: expected `>`".to_string()), parse_path("<u8 as T"));
+        assert_eq!(Err("at position 2: unexpected trailing characters".to_string()),
+                   parse_type("u8)"));
+        assert!(parse_path("foo::").is_err());
+        assert!(parse_type("*u8").is_err());
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        assert_eq!(parse_type(&format!("{}u8", "&".repeat(1000))),
+                   Err("at position 500: text is nested too deeply".to_string()));
+        assert!(parse_path(&"a<".repeat(1000)).is_err());
+        assert!(parse_type(&"&".repeat(400)).is_err());
+        assert!(parse_type(&format!("{}u8", "&".repeat(400))).is_ok());
+    }
+}
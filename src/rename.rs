@@ -0,0 +1,174 @@
+//! Renaming crates inside symbols, e.g. for crates that were vendored under
+//! another name or rebuilt with another disambiguator.
+
+use ast::*;
+use ast_mangle::mangle;
+use fold::{walk_path, Folder, SharedFolds};
+use parse;
+use alloc::string::{String, ToString};
+
+/// Replaces the identifier of every crate root in `symbol` with what
+/// `rename` returns for it, keeping the ones it returns `None` for. This
+/// covers crate roots anywhere in the symbol, e.g. in generic arguments,
+/// dyn traits and the instantiating crate.
+pub fn rename_crates<'input, F>(symbol: &Symbol<'input>, rename: F) -> Symbol<'input>
+    where F: FnMut(&Ident<'input>) -> Option<Ident<'static>>
+{
+    let mut renamer = Renamer {
+        rename,
+        shared: SharedFolds::default(),
+    };
+    renamer.fold_symbol(symbol)
+}
+
+/// Applies `rename_crates` to a mangled symbol and mangles the result again,
+/// which recomputes all back-references. Fails if the symbol does not parse
+/// or a new name cannot be mangled.
+pub fn rename_crates_in_symbol<F>(mangled: &[u8], rename: F) -> Result<String, String>
+    where F: for<'input> FnMut(&Ident<'input>) -> Option<Ident<'static>>
+{
+    let symbol = parse::parse_with_context(mangled).map_err(|e| e.to_string())?;
+    mangle(&rename_crates(&symbol, rename)).map_err(|e| e.to_string())
+}
+
+/// Remembers what each shared node was renamed to, so that the
+/// back-references to it are renamed once and keep sharing the result.
+struct Renamer<'input, F> {
+    rename: F,
+    shared: SharedFolds<'input>,
+}
+
+impl<'input, F> Folder<'input> for Renamer<'input, F>
+    where F: FnMut(&Ident<'input>) -> Option<Ident<'static>>
+{
+    fn fold_path(&mut self, path: &Path<'input>) -> Path<'input> {
+        match *path {
+            Path::CrateRoot { ref id } => {
+                Path::CrateRoot {
+                    id: (self.rename)(id).unwrap_or_else(|| id.clone()),
+                }
+            }
+            _ => walk_path(self, path),
+        }
+    }
+
+    fn shared_folds(&mut self) -> Option<&mut SharedFolds<'input>> {
+        Some(&mut self.shared)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::borrow::Cow;
+    use alloc::sync::Arc;
+    use alloc::vec::Vec;
+    use ast_demangle::AstDemangle;
+    use parse::parse;
+
+    fn crate_name(name: &'static str, dis: u64) -> Ident<'static> {
+        Ident {
+            dis: Base62Number(dis),
+            u_ident: UIdent(Cow::Borrowed(name)),
+        }
+    }
+
+    fn std_to_core(ident: &Ident) -> Option<Ident<'static>> {
+        if ident.u_ident.0 == "std" {
+            Some(crate_name("core", ident.dis.0))
+        } else {
+            None
+        }
+    }
+
+    fn demangle(mangled: &str) -> String {
+        parse(mangled.as_bytes()).unwrap().demangle()
+    }
+
+    #[test]
+    fn crates_behind_backrefs_are_renamed() {
+        let renamed = rename_crates_in_symbol(b"_RINxC3std3fooNtB2_3BarRBb_RBb_E", std_to_core)
+            .unwrap();
+
+        assert_eq!(renamed, "_RINxC4core3fooNtB2_3BarRBc_Bl_E");
+        assert_eq!(demangle(&renamed), "core::foo<core::Bar,&core::Bar,&core::Bar>");
+    }
+
+    #[test]
+    fn shared_nodes_stay_shared() {
+        // std::foo<std::Bar, &std::Bar, &std::Bar>
+        let symbol = parse(b"_RINxC3std3fooNtB2_3BarRBb_RBb_E").unwrap();
+        let renamed = rename_crates(&symbol, std_to_core);
+
+        let referenced: Vec<_> = match renamed.path {
+            Path::Generic { ref args, .. } => {
+                args[1..].iter().map(|arg| match *arg {
+                    GenericArg::Type(Type::Ref(_, ref ty)) => ty.clone(),
+                    _ => unreachable!(),
+                }).collect()
+            }
+            _ => unreachable!(),
+        };
+
+        assert!(Arc::ptr_eq(&referenced[0], &referenced[1]));
+        assert_eq!(renamed.demangle(), "core::foo<core::Bar,&core::Bar,&core::Bar>");
+    }
+
+    #[test]
+    fn shared_fn_sigs_and_dyn_bounds_stay_shared() {
+        fn args<'a, 'input>(symbol: &'a Symbol<'input>) -> &'a [GenericArg<'input>] {
+            match symbol.path {
+                Path::Generic { ref args, .. } => args,
+                _ => unreachable!(),
+            }
+        }
+
+        // std::Foo<fn(std::Bar), fn(std::Bar)>
+        let symbol = parse(b"_RINvC3std3FooFG_NtB2_3BarEuBb_E").unwrap();
+        let renamed = rename_crates(&symbol, std_to_core);
+
+        match *args(&renamed) {
+            [GenericArg::Type(Type::Fn(ref first)), GenericArg::Type(Type::Fn(ref second))] => {
+                assert!(Arc::ptr_eq(first, second));
+            }
+            _ => unreachable!(),
+        }
+
+        // std::Foo<dyn std::Iterator, dyn std::Iterator>
+        let symbol = parse(b"_RINvC3std3FooDG_NtB2_8IteratorEL_Bb_E").unwrap();
+        let renamed = rename_crates(&symbol, std_to_core);
+
+        match *args(&renamed) {
+            [GenericArg::Type(Type::DynTrait(ref first, _)),
+             GenericArg::Type(Type::DynTrait(ref second, _))] => {
+                assert!(Arc::ptr_eq(first, second));
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(renamed.demangle(), "core::Foo<core::Iterator,core::Iterator>");
+    }
+
+    #[test]
+    fn crates_in_dyn_traits_and_fn_sigs_are_renamed() {
+        let renamed = rename_crates_in_symbol(b"_RINvC3std3FooDG_NtB2_8Iteratorp4ItemBe_EL_E",
+                                              std_to_core).unwrap();
+        assert_eq!(demangle(&renamed), "core::Foo<core::Iterator<Item=core::Iterator>>");
+
+        let renamed = rename_crates_in_symbol(b"_RINvC3std3FooFG_NtB2_3BarEBe_E", std_to_core)
+            .unwrap();
+        assert_eq!(demangle(&renamed), "core::Foo<fn(core::Bar) -> core::Bar>");
+    }
+
+    #[test]
+    fn disambiguators_and_the_instantiating_crate_are_renamed() {
+        let renamed = rename_crates_in_symbol(b"_RINxC7mycrate3barmEC16some_other_crate", |id| {
+            match &id.u_ident.0[..] {
+                "mycrate" => Some(crate_name("mycrate_v2", 8)),
+                "some_other_crate" => Some(crate_name("other", 0)),
+                _ => None,
+            }
+        }).unwrap();
+
+        assert_eq!(renamed, "_RINxCs6_10mycrate_v23barmEC5other");
+    }
+}
@@ -0,0 +1,447 @@
+use ast::*;
+use parse::{self, Production};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem;
+use core::ops::Range;
+
+/// A reference to one of the AST nodes that a `SourceMap` keeps track of.
+#[derive(Copy, Clone, Debug)]
+pub enum Node<'a> {
+    Path(&'a Path<'a>),
+    Type(&'a Type<'a>),
+    Const(&'a Const<'a>),
+    Ident(&'a Ident<'a>),
+}
+
+impl<'a> Node<'a> {
+    #[cfg(test)]
+    fn is(&self, other: &Node<'_>) -> bool {
+        match (*self, *other) {
+            (Node::Path(a), Node::Path(b)) => ::core::ptr::eq(a, b),
+            (Node::Type(a), Node::Type(b)) => ::core::ptr::eq(a, b),
+            (Node::Const(a), Node::Const(b)) => ::core::ptr::eq(a, b),
+            (Node::Ident(a), Node::Ident(b)) => ::core::ptr::eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// Identifies the node by its address. The nodes stored in `symbol`
+    /// itself move along with it, so they are identified by their offset
+    /// in it instead.
+    fn key(&self, symbol: &Symbol<'_>) -> NodeKey {
+        let (kind, address) = match *self {
+            Node::Path(path) => (0, path as *const Path as usize),
+            Node::Type(ty) => (1, ty as *const Type as usize),
+            Node::Const(k) => (2, k as *const Const as usize),
+            Node::Ident(ident) => (3, ident as *const Ident as usize),
+        };
+
+        let base = symbol as *const Symbol as usize;
+
+        if address >= base && address < base + mem::size_of::<Symbol>() {
+            (kind, true, address - base)
+        } else {
+            (kind, false, address)
+        }
+    }
+}
+
+type NodeKey = (u8, bool, usize);
+
+/// Where in the mangled input a node comes from.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct NodeSpan {
+    /// The bytes the node was parsed from. For nodes reached through a
+    /// back-reference this is the range of the original occurrence.
+    pub range: Range<usize>,
+    /// The back-reference through which the node was reached, if any. This
+    /// is the part of the input that stands in for the node at the place
+    /// where it shows up in the AST.
+    pub backref: Option<Range<usize>>,
+}
+
+/// A side table mapping the `Path`, `Type`, `Const` and `Ident` nodes of a
+/// parsed symbol to the byte ranges they were parsed from.
+///
+/// The table does not hold on to the AST. Nodes are identified by their
+/// address, so the same `Symbol` has to be passed in when looking them up.
+/// A node shared by back-references has a single span, the one of the
+/// place where the parser first reached it.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SourceMap {
+    spans: BTreeMap<NodeKey, NodeSpan>,
+}
+
+/// Parses a mangled symbol and records a `SourceMap` for it.
+pub fn parse_with_source_map(input: &[u8]) -> Result<(Symbol<'_>, SourceMap), String> {
+    let (symbol, spans) = parse::parse_with_literal_spans(input)?;
+
+    let mut spans = spans.into_iter().filter(|span| {
+        matches!(span.production,
+                 Production::Path |
+                 Production::Type |
+                 Production::Const |
+                 Production::Ident |
+                 Production::Backref)
+    });
+
+    let mut node_spans = BTreeMap::new();
+    // The nodes spelled out at each position, to find the originals of
+    // the copies the parser made at back-references
+    let mut at: BTreeMap<(Production, usize), Node> = BTreeMap::new();
+
+    walk(&symbol, true, &mut |node| {
+        let span = spans.next().expect("a span for every node");
+
+        if span.production != Production::Backref {
+            at.insert((span.production, span.start), node);
+            node_spans.insert(node.key(&symbol), NodeSpan {
+                range: span.start .. span.end,
+                backref: None,
+            });
+            return true;
+        }
+
+        // Only the target of a back-reference is recorded, not what it
+        // contains, which has been walked at the target already
+        let target = spans.next().expect("a span for the target of a back-reference");
+        let backref = span.start .. span.end;
+
+        // Nodes that are not shared through an `Arc` were copied from the
+        // ones at the target and take their spans. A type can refer back to
+        // a path, which is then copied into a new named type.
+        let copies = match (at.get(&(target.production, target.start)), node) {
+            (Some(&original), _) => {
+                inline_nodes(node).into_iter().zip(inline_nodes(original)).skip(1).collect()
+            }
+            (None, Node::Type(Type::Named(path))) => {
+                match at.get(&(Production::Path, target.start)) {
+                    Some(&original) => {
+                        inline_nodes(Node::Path(path)).into_iter()
+                            .zip(inline_nodes(original))
+                            .collect()
+                    }
+                    None => Vec::new(),
+                }
+            }
+            _ => Vec::new(),
+        };
+
+        for (copy, original) in copies {
+            let range = node_spans[&original.key(&symbol)].range.clone();
+            node_spans.entry(copy.key(&symbol)).or_insert(NodeSpan {
+                range,
+                backref: Some(backref.clone()),
+            });
+        }
+
+        at.insert((target.production, span.start), node);
+        node_spans.entry(node.key(&symbol)).or_insert(NodeSpan {
+            range: target.start .. target.end,
+            backref: Some(backref),
+        });
+        false
+    });
+
+    debug_assert!(spans.next().is_none());
+
+    Ok((symbol, SourceMap { spans: node_spans }))
+}
+
+impl SourceMap {
+    /// Iterates over the nodes of `symbol` together with their spans. Each
+    /// node shows up once, and the nodes inside of one that was reached
+    /// through a back-reference only if they are spelled out elsewhere.
+    /// `symbol` must be the symbol this source map was created for.
+    pub fn iter<'a>(&'a self, symbol: &'a Symbol<'a>) -> impl Iterator<Item = (Node<'a>, &'a NodeSpan)> {
+        let mut nodes = Vec::new();
+        let mut seen = BTreeSet::new();
+
+        walk(symbol, true, &mut |node| {
+            let key = node.key(symbol);
+
+            if !seen.insert(key) {
+                return false;
+            }
+
+            let span = &self.spans[&key];
+            nodes.push((node, span));
+            span.backref.is_none()
+        });
+
+        nodes.into_iter()
+    }
+
+    /// Looks up the span of `node`, which must be part of `symbol`.
+    pub fn span_of(&self, symbol: &Symbol<'_>, node: Node<'_>) -> Option<&NodeSpan> {
+        self.spans.get(&node.key(symbol))
+    }
+}
+
+/// `node` and the nodes it contains that are not behind an `Arc`.
+fn inline_nodes<'a>(node: Node<'a>) -> Vec<Node<'a>> {
+    let mut nodes = Vec::new();
+    let mut walker = Walker {
+        visit: &mut |node| {
+            nodes.push(node);
+            true
+        },
+        through_arcs: false,
+    };
+
+    match node {
+        Node::Path(path) => walker.path(path),
+        Node::Type(ty) => walker.ty(ty),
+        Node::Const(k) => walker.konst(k),
+        Node::Ident(ident) => walker.ident(ident),
+    }
+
+    nodes
+}
+
+/// Walks the nodes of `symbol` in the order the parser encounters them,
+/// only going into the nodes `visit` returns `true` for.
+fn walk<'a, F>(symbol: &'a Symbol<'a>, through_arcs: bool, visit: &mut F)
+    where F: FnMut(Node<'a>) -> bool
+{
+    let mut walker = Walker { visit, through_arcs };
+
+    walker.path(&symbol.path);
+
+    if let Some(ref instantiating_crate) = symbol.instantiating_crate {
+        walker.path(instantiating_crate);
+    }
+}
+
+struct Walker<'v, F: 'v> {
+    visit: &'v mut F,
+    // Whether to go into the children behind an `Arc`.
+    through_arcs: bool,
+}
+
+impl<'v, 'a, F> Walker<'v, F>
+    where F: FnMut(Node<'a>) -> bool
+{
+    fn path(&mut self, path: &'a Path<'a>) {
+        if !(self.visit)(Node::Path(path)) {
+            return;
+        }
+
+        match *path {
+            Path::CrateRoot { ref id } => {
+                self.ident(id);
+            }
+            Path::InherentImpl { ref impl_path, ref self_type } => {
+                self.shared_path(&impl_path.path);
+                self.ty(self_type);
+            }
+            Path::TraitImpl { ref impl_path, ref self_type, ref trait_name } => {
+                self.shared_path(&impl_path.path);
+                self.ty(self_type);
+                self.shared_path(trait_name);
+            }
+            Path::TraitDef { ref self_type, ref trait_name } => {
+                self.ty(self_type);
+                self.shared_path(trait_name);
+            }
+            Path::Nested { ns: _, ref inner, ref ident } => {
+                self.shared_path(inner);
+
+                if let Some(ref ident) = *ident {
+                    self.ident(ident);
+                }
+            }
+            Path::Generic { ref inner, ref args } => {
+                self.shared_path(inner);
+
+                for arg in args {
+                    match *arg {
+                        GenericArg::Lifetime(_) => {}
+                        GenericArg::Type(ref ty) => self.ty(ty),
+                        GenericArg::Const(ref k) => self.konst(k),
+                    }
+                }
+            }
+            Path::Invalid => {}
+        }
+    }
+
+    fn ty(&mut self, ty: &'a Type<'a>) {
+        if !(self.visit)(Node::Type(ty)) {
+            return;
+        }
+
+        match *ty {
+            Type::BasicType(_) |
+            Type::Invalid => {}
+            Type::Array(ref inner, ref len) => {
+                self.shared_type(inner);
+
+                if self.through_arcs {
+                    self.konst(len);
+                }
+            }
+            Type::Named(ref path) => {
+                self.shared_path(path);
+            }
+            Type::Tuple(ref components) => {
+                for component in components {
+                    self.ty(component);
+                }
+            }
+            Type::Slice(ref inner) |
+            Type::Ref(_, ref inner) |
+            Type::RefMut(_, ref inner) |
+            Type::RawPtrConst(ref inner) |
+            Type::RawPtrMut(ref inner) => {
+                self.shared_type(inner);
+            }
+            Type::Fn(ref fn_sig) if self.through_arcs => {
+                for param_type in fn_sig.param_types.iter() {
+                    self.ty(param_type);
+                }
+                self.ty(&fn_sig.return_type);
+            }
+            Type::DynTrait(ref bounds, _) if self.through_arcs => {
+                for tr in bounds.traits.iter() {
+                    self.path(&tr.path);
+
+                    for binding in tr.assoc_type_bindings.iter() {
+                        self.ty(&binding.ty);
+                    }
+                }
+            }
+            Type::Fn(_) |
+            Type::DynTrait(..) => {}
+        }
+    }
+
+    fn konst(&mut self, k: &'a Const<'a>) {
+        if !(self.visit)(Node::Const(k)) {
+            return;
+        }
+
+        match *k {
+            Const::Value(ref ty, _) |
+            Const::Placeholder(ref ty) => {
+                self.ty(ty);
+            }
+            Const::Invalid => {}
+        }
+    }
+
+    fn ident(&mut self, ident: &'a Ident<'a>) {
+        (self.visit)(Node::Ident(ident));
+    }
+
+    fn shared_path(&mut self, path: &'a Arc<Path<'a>>) {
+        if self.through_arcs {
+            self.path(path);
+        }
+    }
+
+    fn shared_type(&mut self, ty: &'a Arc<Type<'a>>) {
+        if self.through_arcs {
+            self.ty(ty);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans_of_nodes() {
+        let mangled = b"_RNxXC3stdNyNyB2_3foo3FooB9_3bar";
+        let (symbol, source_map) = parse_with_source_map(mangled).unwrap();
+
+        let (trait_name, self_type) = match symbol.path {
+            Path::Nested { ref inner, .. } => match **inner {
+                Path::TraitImpl { ref trait_name, ref self_type, .. } => (trait_name.clone(), self_type),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+
+        assert_eq!(Some(&NodeSpan { range: 2 .. 32, backref: None }),
+                   source_map.span_of(&symbol, Node::Path(&symbol.path)));
+
+        assert_eq!(Some(&NodeSpan { range: 10 .. 25, backref: None }),
+                   source_map.span_of(&symbol, Node::Type(self_type)));
+
+        // `B9_` refers back to the `NyB2_3foo` inside of the self type, which
+        // is the same node
+        assert_eq!(Some(&NodeSpan { range: 12 .. 21, backref: None }),
+                   source_map.span_of(&symbol, Node::Path(&trait_name)));
+
+        let trait_name_spans: Vec<_> = source_map
+            .iter(&symbol)
+            .filter(|&(node, _)| node.is(&Node::Path(&trait_name)))
+            .map(|(_, span)| span.clone())
+            .collect();
+
+        assert_eq!(vec![NodeSpan { range: 12 .. 21, backref: None }], trait_name_spans);
+    }
+
+    #[test]
+    fn copies_at_backrefs_take_the_spans_of_the_originals() {
+        // std::foo<(std::Bar, ()), (std::Bar, ())>
+        let mangled = b"_RINxC3std3fooTNtB2_3BaruEBb_E";
+        let (symbol, source_map) = parse_with_source_map(mangled).unwrap();
+
+        let args = match symbol.path {
+            Path::Generic { ref args, .. } => args,
+            _ => unreachable!(),
+        };
+
+        let spans: Vec<_> = args.iter().map(|arg| match *arg {
+            GenericArg::Type(ref ty @ Type::Tuple(ref components)) => {
+                (source_map.span_of(&symbol, Node::Type(ty)).cloned(),
+                 source_map.span_of(&symbol, Node::Type(&components[0])).cloned())
+            }
+            _ => unreachable!(),
+        }).collect();
+
+        assert_eq!(vec![
+            (Some(NodeSpan { range: 14 .. 26, backref: None }),
+             Some(NodeSpan { range: 15 .. 24, backref: None })),
+            (Some(NodeSpan { range: 14 .. 26, backref: Some(26 .. 29) }),
+             Some(NodeSpan { range: 15 .. 24, backref: Some(26 .. 29) })),
+        ], spans);
+    }
+
+    #[test]
+    fn every_node_has_a_span() {
+        for mangled in ::test_data::valid_symbols() {
+            let (symbol, source_map) = parse_with_source_map(mangled.as_bytes()).unwrap();
+
+            walk(&symbol, true, &mut |node| {
+                assert!(source_map.span_of(&symbol, node).is_some(), "{}: {:?}", mangled, node);
+                true
+            });
+        }
+    }
+
+    #[test]
+    fn symbols_can_be_moved() {
+        let mangled = b"_RNxXC3stdNyNyB2_3foo3FooB9_3bar";
+        let (symbol, source_map) = parse_with_source_map(mangled).unwrap();
+        let symbol = Some(symbol);
+        let symbol = symbol.as_ref().unwrap();
+
+        assert_eq!(Some(&NodeSpan { range: 2 .. 32, backref: None }),
+                   source_map.span_of(symbol, Node::Path(&symbol.path)));
+    }
+
+    #[test]
+    fn nested_backrefs_are_mapped_once() {
+        let mangled = ::generate::nested_backrefs(64);
+        let (symbol, source_map) = parse_with_source_map(mangled.as_bytes()).unwrap();
+
+        assert!(source_map.iter(&symbol).count() < mangled.len());
+    }
+}
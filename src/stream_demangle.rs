@@ -0,0 +1,1052 @@
+use ast::Base62Number;
+use builtin_punycode;
+use int_radix::ascii_digit_to_value;
+use parse::{Production, EOT};
+use alloc::string::{String, ToString};
+use core::fmt::{self, Write};
+use core::str;
+
+/// How deeply paths and types may be nested, counting the nesting through
+/// back-references. This bounds stack usage and rejects back-references to
+/// an enclosing production, which would never terminate.
+const MAX_DEPTH: usize = 500;
+
+/// The number of chars a punycode identifier may decode to. Identifiers are
+/// decoded into a buffer of this size, so that demangling never allocates.
+const MAX_PUNYCODE_CHARS: usize = 256;
+
+/// How many bytes the output of demangling may have. Nested back-references
+/// can make the output grow exponentially with the length of the input, so
+/// demangling stops at this length rather than producing all of it.
+pub const MAX_DEMANGLED_LEN: usize = 1 << 20;
+
+/// An error that occurred while demangling. Unlike the errors of the parser
+/// these do not allocate, so that they can be used where the heap cannot.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DemangleError {
+    /// The input is not a valid symbol. `position` is the offset at which
+    /// the problem was detected.
+    Invalid { position: usize, reason: &'static str },
+    /// The output did not fit into the buffer passed to `demangle_into`,
+    /// which holds as much of it as did fit. `required` is the length of
+    /// the complete output.
+    Truncated { required: usize },
+    /// The output would be longer than `MAX_DEMANGLED_LEN` bytes.
+    TooLong,
+    /// The `Write` implementation the output was passed to failed.
+    Write,
+}
+
+impl fmt::Display for DemangleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DemangleError::Invalid { position, reason } => {
+                write!(f, "at position {}: {}", position, reason)
+            }
+            DemangleError::Truncated { required } => {
+                write!(f, "output buffer too small, {} bytes required", required)
+            }
+            DemangleError::TooLong => {
+                write!(f, "output longer than {} bytes", MAX_DEMANGLED_LEN)
+            }
+            DemangleError::Write => write!(f, "could not write output"),
+        }
+    }
+}
+
+/// Demangles a symbol in a single pass, writing the output to `out` as the
+/// input is read. No AST is constructed; back-references are resolved by
+/// jumping back to their target within the input. The output is the same as
+/// the one of `parse::parse` followed by `AstDemangle::demangle`.
+pub fn demangle<W: Write>(input: &[u8], out: &mut W) -> Result<(), String> {
+    demangle_to_writer(input, out).map_err(|e| e.to_string())
+}
+
+/// Demangles a symbol into `buf` without allocating, returning the length
+/// of the output. The output is always valid UTF-8. If it does not fit,
+/// `buf` holds the longest prefix of it that ends at a char boundary.
+pub fn demangle_into(input: &[u8], buf: &mut [u8]) -> Result<usize, DemangleError> {
+    let mut writer = SliceWriter {
+        buf,
+        len: 0,
+        required: 0,
+    };
+
+    demangle_to_writer(input, &mut writer)?;
+
+    if writer.required > writer.len {
+        return Err(DemangleError::Truncated { required: writer.required });
+    }
+
+    Ok(writer.len)
+}
+
+fn demangle_to_writer<W: Write>(input: &[u8], out: &mut W) -> Result<(), DemangleError> {
+    Demangler::new(input, out, true).demangle_symbol()
+}
+
+/// Demangles the `production` at `pos` of a valid symbol on its own, for
+/// describing the parts of the symbol. Back-references are not followed but
+/// shown as `{B<n>_ -> <offset>}`, so the output stays proportional to the
+/// length of the production.
+pub(crate) fn demangle_production_at<W: Write>(input: &[u8],
+                                               pos: usize,
+                                               production: Production,
+                                               out: &mut W)
+                                               -> Result<(), DemangleError> {
+    let mut demangler = Demangler::new(input, out, false);
+    demangler.pos = pos;
+
+    match production {
+        Production::Path => demangler.demangle_path(),
+        Production::Type => demangler.demangle_type(),
+        Production::Const => demangler.demangle_const(),
+        _ => demangler.demangle_ident(),
+    }
+}
+
+/// Writes into a fixed buffer. Once a write does not fit, all further
+/// output is only counted.
+struct SliceWriter<'buf> {
+    buf: &'buf mut [u8],
+    len: usize,
+    required: usize,
+}
+
+impl<'buf> Write for SliceWriter<'buf> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.required == self.len {
+            let available = self.buf.len() - self.len;
+            let mut n = s.len().min(available);
+
+            while !s.is_char_boundary(n) {
+                n -= 1;
+            }
+
+            self.buf[self.len .. self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+            self.len += n;
+        }
+
+        self.required += s.len();
+        Ok(())
+    }
+}
+
+/// Fails once more than `MAX_DEMANGLED_LEN` bytes were written.
+struct LimitedWriter<'a, W: Write + 'a> {
+    out: &'a mut W,
+    written: &'a mut usize,
+}
+
+impl<'a, W: Write> Write for LimitedWriter<'a, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        *self.written += s.len();
+
+        if *self.written > MAX_DEMANGLED_LEN {
+            return Err(fmt::Error);
+        }
+
+        self.out.write_str(s)
+    }
+}
+
+struct Demangler<'input, 'out, W: Write + 'out> {
+    input: &'input [u8],
+    pos: usize,
+    out: &'out mut W,
+    // The length of the output so far, see `MAX_DEMANGLED_LEN`.
+    written: usize,
+    // While non-zero, productions are parsed without producing output.
+    silent: usize,
+    depth: usize,
+    // Where each of the productions that are being demangled starts.
+    starts: [usize; MAX_DEPTH],
+    // Whether to demangle the targets of back-references in their place.
+    follow_backrefs: bool,
+    // Holds the most recently parsed punycode identifier.
+    punycode_buf: [char; MAX_PUNYCODE_CHARS],
+}
+
+/// An identifier, either as found in the input or, for punycode
+/// identifiers, as the number of chars decoded into `punycode_buf`.
+enum Name<'input> {
+    Plain(&'input str),
+    Punycode(usize),
+}
+
+impl<'input> Name<'input> {
+    fn is_empty(&self) -> bool {
+        match *self {
+            Name::Plain(name) => name.is_empty(),
+            Name::Punycode(len) => len == 0,
+        }
+    }
+}
+
+impl<'input, 'out, W: Write> Demangler<'input, 'out, W> {
+    fn new(input: &'input [u8], out: &'out mut W, follow_backrefs: bool) -> Self {
+        Demangler {
+            input,
+            pos: 0,
+            out,
+            written: 0,
+            silent: 0,
+            depth: 0,
+            starts: [0; MAX_DEPTH],
+            follow_backrefs,
+            punycode_buf: ['\0'; MAX_PUNYCODE_CHARS],
+        }
+    }
+
+    fn demangle_symbol(&mut self) -> Result<(), DemangleError> {
+        if !self.input.starts_with(b"_R") {
+            return Err(self.invalid("not a Rust symbol"));
+        }
+
+        self.pos += 2;
+
+        if self.cur().is_ascii_digit() {
+            self.parse_number(10)?;
+            return Err(self.invalid("unsupported encoding version"));
+        }
+
+        self.demangle_path()?;
+
+        if self.cur() != EOT {
+            self.write(" @ ")?;
+            self.demangle_path()?;
+        }
+
+        if self.cur() != EOT {
+            return Err(self.invalid("unexpected trailing characters"));
+        }
+
+        Ok(())
+    }
+
+    fn demangle_path(&mut self) -> Result<(), DemangleError> {
+        self.nested(Demangler::demangle_path_inner)
+    }
+
+    fn demangle_path_inner(&mut self) -> Result<(), DemangleError> {
+        let tag = self.cur();
+        self.pos += 1;
+
+        match tag {
+            b'C' => {
+                self.demangle_ident()?;
+            }
+            b'M' => {
+                self.skip_impl_path()?;
+                self.write("<")?;
+                self.demangle_type()?;
+                self.write(">")?;
+            }
+            b'X' => {
+                self.skip_impl_path()?;
+                self.demangle_qualified_self_type()?;
+            }
+            b'Y' => {
+                self.demangle_qualified_self_type()?;
+            }
+            b'N' => {
+                let ns = self.cur();
+                match ns {
+                    b'A' ..= b'Z' | b'a' ..= b'z' => {}
+                    _ => return Err(self.invalid("invalid namespace character")),
+                };
+                self.pos += 1;
+
+                self.demangle_path()?;
+
+                let (dis, name) = self.parse_ident()?;
+
+                if ns == b'C' {
+                    self.write("::{closure}")?;
+                    self.write_fmt(format_args!("[{}]", dis))?;
+                } else if !name.is_empty() {
+                    self.write("::")?;
+                    self.write_ident(dis, name)?;
+                }
+            }
+            b'I' => {
+                self.demangle_path()?;
+                self.write("<")?;
+
+                let mut first = true;
+                while self.cur() != b'E' {
+                    if !first {
+                        self.write(",")?;
+                    }
+                    first = false;
+                    self.demangle_generic_arg()?;
+                }
+
+                self.eat(b'E', "expected 'E' after generic arguments")?;
+                self.write(">")?;
+            }
+            b'B' => {
+                self.pos -= 1;
+                self.demangle_backref(Production::Path, Demangler::demangle_path)?;
+            }
+            _ => {
+                self.pos -= 1;
+                return Err(self.invalid("expected start of <path>"));
+            }
+        }
+
+        Ok(())
+    }
+
+    // `<self-type as trait-name>`, as found in trait impls and definitions.
+    fn demangle_qualified_self_type(&mut self) -> Result<(), DemangleError> {
+        self.write("<")?;
+        self.demangle_type()?;
+        self.write(" as ")?;
+        self.demangle_path()?;
+        self.write(">")
+    }
+
+    fn skip_impl_path(&mut self) -> Result<(), DemangleError> {
+        if self.cur() == b's' {
+            self.parse_disambiguator()?;
+        }
+
+        self.silent += 1;
+        let result = self.demangle_path();
+        self.silent -= 1;
+        result
+    }
+
+    fn demangle_generic_arg(&mut self) -> Result<(), DemangleError> {
+        match self.cur() {
+            b'L' => {
+                self.skip_lifetime()?;
+                self.write("'_")
+            }
+            b'K' => {
+                self.pos += 1;
+                self.demangle_const()
+            }
+            _ => {
+                self.demangle_type()
+            }
+        }
+    }
+
+    fn demangle_type(&mut self) -> Result<(), DemangleError> {
+        self.nested(Demangler::demangle_type_inner)
+    }
+
+    fn demangle_type_inner(&mut self) -> Result<(), DemangleError> {
+        let tag = self.cur();
+        self.pos += 1;
+
+        if let Some(name) = basic_type_name(tag) {
+            return self.write(name);
+        }
+
+        match tag {
+            b'A' => {
+                self.write("[")?;
+                self.demangle_type()?;
+                self.write("; ")?;
+                self.demangle_const()?;
+                self.write("]")?;
+            }
+            b'S' => {
+                self.write("[")?;
+                self.demangle_type()?;
+                self.write("]")?;
+            }
+            b'C' | b'M' | b'X' | b'Y' | b'N' | b'I' => {
+                self.pos -= 1;
+                self.demangle_path()?;
+            }
+            b'T' => {
+                self.write("(")?;
+
+                let mut first = true;
+                while self.cur() != b'E' {
+                    if !first {
+                        self.write(",")?;
+                    }
+                    first = false;
+                    self.demangle_type()?;
+                }
+
+                self.eat(b'E', "expected 'E' after tuple components")?;
+                self.write(")")?;
+            }
+            b'R' | b'Q' => {
+                if self.cur() == b'L' {
+                    self.skip_lifetime()?;
+                }
+
+                self.write(if tag == b'R' { "&" } else { "&mut " })?;
+                self.demangle_type()?;
+            }
+            b'P' => {
+                self.write("*const ")?;
+                self.demangle_type()?;
+            }
+            b'O' => {
+                self.write("*mut ")?;
+                self.demangle_type()?;
+            }
+            b'F' => {
+                self.demangle_fn_sig()?;
+            }
+            b'D' => {
+                self.demangle_dyn_bounds()?;
+                self.skip_lifetime()?;
+            }
+            b'B' => {
+                self.pos -= 1;
+                self.demangle_backref(Production::Type, Demangler::demangle_type)?;
+            }
+            _ => {
+                self.pos -= 1;
+                return Err(self.invalid("expected start of <type>"));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn skip_type(&mut self) -> Result<(), DemangleError> {
+        self.silent += 1;
+        let result = self.demangle_type();
+        self.silent -= 1;
+        result
+    }
+
+    fn demangle_fn_sig(&mut self) -> Result<(), DemangleError> {
+        self.skip_binder()?;
+
+        if self.try_eat(b'U') {
+            self.write("unsafe ")?;
+        }
+
+        if self.try_eat(b'K') {
+            self.write("extern \"")?;
+
+            if self.try_eat(b'C') {
+                self.write("C")?;
+            } else {
+                let name = self.parse_uident()?;
+                self.write_name(name)?;
+            }
+
+            self.write("\" ")?;
+        }
+
+        self.write("fn(")?;
+
+        let mut first = true;
+        while self.cur() != b'E' {
+            if !first {
+                self.write(",")?;
+            }
+            first = false;
+            self.demangle_type()?;
+        }
+
+        self.eat(b'E', "expected 'E' after parameter types")?;
+        self.write(")")?;
+
+        if self.resolved_tag(self.pos)? == b'u' {
+            self.skip_type()?;
+        } else {
+            self.write(" -> ")?;
+            self.demangle_type()?;
+        }
+
+        Ok(())
+    }
+
+    fn demangle_dyn_bounds(&mut self) -> Result<(), DemangleError> {
+        self.skip_binder()?;
+
+        let mut first = true;
+        while self.cur() != b'E' {
+            if !first {
+                self.write("+")?;
+            }
+            first = false;
+            self.demangle_dyn_trait()?;
+        }
+
+        self.eat(b'E', "expected 'E' after trait object bounds")
+    }
+
+    fn demangle_dyn_trait(&mut self) -> Result<(), DemangleError> {
+        self.demangle_path()?;
+
+        if self.cur() == b'p' {
+            self.write("<")?;
+
+            let mut first = true;
+            while self.try_eat(b'p') {
+                if !first {
+                    self.write(", ")?;
+                }
+                first = false;
+
+                let name = self.parse_uident()?;
+                self.write_name(name)?;
+                self.write("=")?;
+                self.demangle_type()?;
+            }
+
+            self.write(">")?;
+        }
+
+        Ok(())
+    }
+
+    fn demangle_const(&mut self) -> Result<(), DemangleError> {
+        self.nested(Demangler::demangle_const_inner)
+    }
+
+    fn demangle_const_inner(&mut self) -> Result<(), DemangleError> {
+        if self.cur() == b'B' {
+            return self.demangle_backref(Production::Const, Demangler::demangle_const);
+        }
+
+        // Whether the type is printed depends on what follows it, so it is
+        // skipped first and revisited afterwards if needed.
+        let type_pos = self.pos;
+        self.skip_type()?;
+
+        if self.try_eat(b'p') {
+            self.demangle_const_type_at(type_pos)
+        } else {
+            let value = self.parse_number(16)?;
+            self.eat(b'_', "expected '_' after constant value")?;
+
+            if is_integer_type(self.resolved_tag(type_pos)?) {
+                self.write_fmt(format_args!("{}", value))
+            } else {
+                self.demangle_const_type_at(type_pos)
+            }
+        }
+    }
+
+    fn demangle_const_type_at(&mut self, type_pos: usize) -> Result<(), DemangleError> {
+        let return_pos = self.pos;
+
+        self.write("{const ")?;
+        self.pos = type_pos;
+        self.demangle_type()?;
+        self.pos = return_pos;
+        self.write("}")
+    }
+
+    fn demangle_ident(&mut self) -> Result<(), DemangleError> {
+        let (dis, name) = self.parse_ident()?;
+        self.write_ident(dis, name)
+    }
+
+    fn write_ident(&mut self, dis: u64, name: Name) -> Result<(), DemangleError> {
+        self.write_name(name)?;
+
+        if dis != 0 {
+            self.write_fmt(format_args!("[{}]", dis))?;
+        }
+
+        Ok(())
+    }
+
+    fn write_name(&mut self, name: Name) -> Result<(), DemangleError> {
+        match name {
+            Name::Plain(name) => self.write(name),
+            Name::Punycode(len) => {
+                for index in 0 .. len {
+                    let c = self.punycode_buf[index];
+                    self.write(c.encode_utf8(&mut [0; 4]))?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    fn parse_ident(&mut self) -> Result<(u64, Name<'input>), DemangleError> {
+        let dis = if self.cur() == b's' {
+            self.parse_disambiguator()?
+        } else {
+            0
+        };
+
+        Ok((dis, self.parse_uident()?))
+    }
+
+    fn parse_disambiguator(&mut self) -> Result<u64, DemangleError> {
+        self.eat(b's', "expected 's' at start of disambiguator")?;
+
+        match self.parse_base62_number()?.0.checked_add(1) {
+            Some(dis) => Ok(dis),
+            None => Err(self.invalid("number is too large")),
+        }
+    }
+
+    fn parse_uident(&mut self) -> Result<Name<'input>, DemangleError> {
+        let punycode = self.try_eat(b'u');
+        let num_bytes = self.parse_number(10)?;
+        let start = self.pos;
+
+        if num_bytes > (self.input.len() - start) as u64 {
+            return Err(self.invalid("identifier extends beyond end of input"));
+        }
+
+        let input = self.input;
+        let bytes = &input[start .. start + num_bytes as usize];
+
+        let name = if punycode {
+            // A punycode identifier never decodes to more chars than it has bytes
+            if bytes.len() > MAX_PUNYCODE_CHARS {
+                return Err(self.invalid("punycode identifier is too long"));
+            }
+
+            match builtin_punycode::decode_v0_ident_into(bytes, &mut self.punycode_buf) {
+                Some(len) => Name::Punycode(len),
+                None => return Err(self.invalid("invalid punycode identifier")),
+            }
+        } else {
+            match str::from_utf8(bytes) {
+                Ok(name) => Name::Plain(name),
+                Err(_) => return Err(self.invalid("identifier is not valid UTF-8")),
+            }
+        };
+
+        self.pos += bytes.len();
+
+        Ok(name)
+    }
+
+    fn skip_lifetime(&mut self) -> Result<(), DemangleError> {
+        self.eat(b'L', "expected 'L' at start of lifetime")?;
+        self.parse_base62_number()?;
+        Ok(())
+    }
+
+    fn skip_binder(&mut self) -> Result<(), DemangleError> {
+        self.eat(b'G', "expected 'G' at start of binder")?;
+        self.parse_base62_number()?;
+        Ok(())
+    }
+
+    fn parse_base62_number(&mut self) -> Result<Base62Number, DemangleError> {
+        let n = if self.cur() == b'_' {
+            0
+        } else {
+            match self.parse_number(62)?.checked_add(1) {
+                Some(n) => n,
+                None => return Err(self.invalid("number is too large")),
+            }
+        };
+
+        self.eat(b'_', "expected '_' after base-62 number")?;
+
+        Ok(Base62Number(n))
+    }
+
+    fn parse_number(&mut self, radix: u8) -> Result<u64, DemangleError> {
+        if ascii_digit_to_value(self.cur(), radix).is_none() {
+            return Err(self.invalid("expected digit"));
+        }
+
+        let mut value: u64 = 0;
+
+        while let Some(digit) = ascii_digit_to_value(self.cur(), radix) {
+            value = match value.checked_mul(radix as u64).and_then(|v| v.checked_add(digit)) {
+                Some(value) => value,
+                None => return Err(self.invalid("number is too large")),
+            };
+            self.pos += 1;
+        }
+
+        Ok(value)
+    }
+
+    /// Demangles the target of the back-reference at the current position
+    /// via `demangle_target` and continues right after the back-reference.
+    fn demangle_backref(&mut self,
+                        production: Production,
+                        demangle_target: fn(&mut Self) -> Result<(), DemangleError>)
+                        -> Result<(), DemangleError> {
+        let backref_pos = self.pos;
+        let target = self.parse_backref()?;
+        let return_pos = self.pos;
+
+        // Nothing is written while skipping, so there is no need to walk the
+        // target, which takes exponential time for nested back-references.
+        if self.silent > 0 {
+            self.check_backref_target(target, production)?;
+        } else if !self.follow_backrefs {
+            let input = self.input;
+            let backref = str::from_utf8(&input[backref_pos .. return_pos])
+                .expect("back-references are ASCII");
+            self.write_fmt(format_args!("{{{} -> {}}}", backref, target))?;
+        } else {
+            self.pos = target;
+            demangle_target(self)?;
+        }
+
+        self.pos = return_pos;
+        Ok(())
+    }
+
+    /// Checks that `target` starts a `production` that is not one of the
+    /// productions being demangled, as is the case for every back-reference
+    /// in a valid symbol, without walking the target.
+    fn check_backref_target(&mut self,
+                            target: usize,
+                            production: Production)
+                            -> Result<(), DemangleError> {
+        self.pos = target;
+
+        let mut depth = self.depth;
+        loop {
+            if depth == MAX_DEPTH || self.starts[.. self.depth].contains(&self.pos) {
+                return Err(self.invalid("symbol is nested too deeply"));
+            }
+
+            if self.cur() != b'B' {
+                break;
+            }
+
+            depth += 1;
+            self.pos = self.parse_backref()?;
+        }
+
+        let tag = self.cur();
+        let is_path = matches!(tag, b'C' | b'M' | b'X' | b'Y' | b'N' | b'I');
+
+        match production {
+            Production::Path if !is_path => {
+                Err(self.invalid("expected start of <path>"))
+            }
+            Production::Type | Production::Const if !is_path && !is_type_tag(tag) => {
+                Err(self.invalid("expected start of <type>"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn parse_backref(&mut self) -> Result<usize, DemangleError> {
+        let backref_pos = self.pos;
+        self.eat(b'B', "expected 'B' at start of back-reference")?;
+
+        let Base62Number(pos) = self.parse_base62_number()?;
+
+        // Only allowing references to earlier positions makes sure that
+        // following back-references always terminates. The `_R` prefix is
+        // not counted in the encoded position.
+        if pos >= (backref_pos - 2) as u64 {
+            return Err(self.invalid("back-reference does not point backwards"));
+        }
+
+        let target = pos as usize + 2;
+
+        Ok(target)
+    }
+
+    fn nested(&mut self, f: fn(&mut Self) -> Result<(), DemangleError>) -> Result<(), DemangleError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.invalid("symbol is nested too deeply"));
+        }
+
+        self.starts[self.depth] = self.pos;
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// The tag of the type at `pos`, looking through back-references.
+    fn resolved_tag(&mut self, pos: usize) -> Result<u8, DemangleError> {
+        let return_pos = self.pos;
+        self.pos = pos;
+
+        let mut depth = self.depth;
+        while self.cur() == b'B' {
+            if depth == MAX_DEPTH {
+                return Err(self.invalid("symbol is nested too deeply"));
+            }
+            depth += 1;
+            self.pos = self.parse_backref()?;
+        }
+
+        let tag = self.cur();
+        self.pos = return_pos;
+        Ok(tag)
+    }
+
+    fn cur(&self) -> u8 {
+        if self.pos < self.input.len() {
+            self.input[self.pos]
+        } else {
+            EOT
+        }
+    }
+
+    fn eat(&mut self, c: u8, reason: &'static str) -> Result<(), DemangleError> {
+        if self.cur() != c {
+            return Err(self.invalid(reason));
+        }
+
+        self.pos += 1;
+
+        Ok(())
+    }
+
+    fn try_eat(&mut self, c: u8) -> bool {
+        if self.cur() == c {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn write(&mut self, s: &str) -> Result<(), DemangleError> {
+        self.write_fmt(format_args!("{}", s))
+    }
+
+    fn write_fmt(&mut self, args: fmt::Arguments) -> Result<(), DemangleError> {
+        if self.silent == 0 {
+            let mut out = LimitedWriter {
+                out: &mut *self.out,
+                written: &mut self.written,
+            };
+
+            if out.write_fmt(args).is_err() {
+                return Err(if self.written > MAX_DEMANGLED_LEN {
+                    DemangleError::TooLong
+                } else {
+                    DemangleError::Write
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn invalid(&self, reason: &'static str) -> DemangleError {
+        DemangleError::Invalid { position: self.pos, reason }
+    }
+}
+
+fn basic_type_name(tag: u8) -> Option<&'static str> {
+    Some(match tag {
+        b'a' => "i8",
+        b'b' => "bool",
+        b'c' => "char",
+        b'd' => "f64",
+        b'e' => "str",
+        b'f' => "f32",
+        b'h' => "u8",
+        b'i' => "isize",
+        b'j' => "usize",
+        b'l' => "i32",
+        b'm' => "u32",
+        b'n' => "i128",
+        b'o' => "u128",
+        b'p' => "_",
+        b's' => "i16",
+        b't' => "u16",
+        b'u' => "()",
+        b'v' => "...",
+        b'x' => "i64",
+        b'y' => "u64",
+        b'z' => "!",
+        _ => return None,
+    })
+}
+
+/// Whether `tag` starts a type that is not a path or a back-reference.
+fn is_type_tag(tag: u8) -> bool {
+    basic_type_name(tag).is_some() ||
+    matches!(tag, b'A' | b'S' | b'T' | b'R' | b'Q' | b'P' | b'O' | b'F' | b'D')
+}
+
+fn is_integer_type(tag: u8) -> bool {
+    matches!(tag,
+             b'a' | b's' | b'l' | b'x' | b'n' | b'i' |
+             b'h' | b't' | b'm' | b'y' | b'o' | b'j')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast_demangle::AstDemangle;
+    use parse;
+
+    fn assert_same_as_ast_demangle(mangled: &str) {
+        let expected = parse::parse(mangled.as_bytes()).unwrap().demangle();
+
+        let mut actual = String::new();
+        demangle(mangled.as_bytes(), &mut actual).unwrap();
+
+        assert_eq!(expected, actual, "{}", mangled);
+    }
+
+    #[test]
+    fn same_output_as_ast_demangle_for_other_productions() {
+        // Const generic arguments, placeholders and back-references
+        assert_same_as_ast_demangle("_RINxC3std3FooKj7b_KbpKBc_E");
+        // Non-integer const value
+        assert_same_as_ast_demangle("_RINxC3std3FooKc61_E");
+        // Empty tuple and empty generic argument list
+        assert_same_as_ast_demangle("_RINxC3std3FooTEINyB2_3BarEE");
+        // Trait objects with associated type bindings
+        assert_same_as_ast_demangle("_RINxC3std3FooDG_NyB2_4Iterp4Itemhp4Selfu\
+                                       NyB2_4SendEL_E");
+        // Functions returning unit through a back-reference
+        assert_same_as_ast_demangle("_RINxC3std3FoouFG_KCjEBb_E");
+        // Inherent impl and named ABI
+        assert_same_as_ast_demangle("_RNxMs_C3fooFG_K5cdeclEu4quux");
+    }
+
+    fn assert_same_result_as_parser(mangled: &[u8]) {
+        let expected = parse::parse(mangled).map(|symbol| symbol.demangle());
+        let mut actual = String::new();
+
+        match (expected, demangle(mangled, &mut actual)) {
+            (Ok(expected), Ok(())) => assert_eq!(expected, actual),
+            (Err(_), Err(_)) => {}
+            (expected, result) => {
+                panic!("{}: parser {:?}, demangler {:?}",
+                       str::from_utf8(mangled).unwrap(), expected, result)
+            }
+        }
+    }
+
+    #[test]
+    fn mutated_symbols_are_accepted_like_the_parser_does() {
+        // Mutations easily produce back-references to an enclosing
+        // production, which recurse up to `MAX_DEPTH` with the large stack
+        // frames of unoptimized builds
+        let check = ::std::thread::Builder::new().stack_size(64 << 20).spawn(|| {
+            for mangled in ::test_data::valid_symbols() {
+                let mangled = mangled.as_bytes();
+
+                for pos in 2 .. mangled.len() {
+                    assert_same_result_as_parser(&[&mangled[..pos], &mangled[pos + 1..]].concat());
+
+                    for &byte in b"B_0aAzCNMvIEKpu" {
+                        let mut mutated = mangled.to_vec();
+                        mutated[pos] = byte;
+                        assert_same_result_as_parser(&mutated);
+                    }
+                }
+            }
+        });
+
+        check.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn impl_paths_are_checked_without_output() {
+        // The impl path refers back to the `v` of the namespace
+        let mut out = String::new();
+        assert_eq!(demangle(b"_RNvMB0_h3foo", &mut out),
+                   Err("at position 3: expected start of <path>".to_string()));
+        assert!(parse::parse(b"_RNvMB0_h3foo").unwrap_err().starts_with("at position 3: "));
+    }
+
+    #[test]
+    fn forward_backrefs_are_rejected() {
+        let mut out = String::new();
+        assert!(demangle(b"_RNvC3fooB9_", &mut out).is_err());
+    }
+
+    #[test]
+    fn cyclic_backrefs_are_rejected() {
+        let mut out = String::new();
+        assert!(demangle(b"_RNvB_3foo", &mut out).is_err());
+    }
+
+    #[test]
+    fn deep_const_backrefs_are_rejected() {
+        use generate::backref_chain;
+
+        // Every constant refers back to the one before it. Each `K` belongs
+        // to the constant after it, so that back-references point at values.
+        let mangled = backref_chain("_RINxC3std3FooK", "j0_K", 10_000,
+                                    |backref| format!("{}K", backref), "pE");
+
+        assert_eq!(parse::parse_with_context(mangled.as_bytes()).unwrap_err().kind,
+                   parse::ErrorKind::NestedTooDeeply);
+
+        let mut buf = [0; 64];
+        match demangle_into(mangled.as_bytes(), &mut buf) {
+            Err(DemangleError::Invalid { reason, .. }) => {
+                assert_eq!(reason, "symbol is nested too deeply");
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn demangle_into_fitting_buffer() {
+        let mut buf = [0; 64];
+        let len = demangle_into(b"_RINxC3std3FooKj7b_E", &mut buf).unwrap();
+        assert_eq!(b"std::Foo<123>", &buf[..len]);
+
+        let mut buf = [0; 13];
+        assert_eq!(Ok(13), demangle_into(b"_RINxC3std3FooKj7b_E", &mut buf));
+    }
+
+    #[test]
+    fn demangle_into_reports_truncation() {
+        let mut buf = [0; 10];
+        assert_eq!(Err(DemangleError::Truncated { required: 13 }),
+                   demangle_into(b"_RINxC3std3FooKj7b_E", &mut buf));
+        assert_eq!(b"std::Foo<1", &buf);
+    }
+
+    #[test]
+    fn demangle_into_truncates_at_char_boundary() {
+        let mut buf = [0; 12];
+        assert_eq!(Err(DemangleError::Truncated { required: 17 }),
+                   demangle_into(b"_RNvC7mycrateu10mnchen_Dya", &mut buf));
+        // The `ü` just fits
+        assert_eq!(b"mycrate::m\xc3\xbc", &buf);
+
+        let mut buf = [0; 11];
+        assert_eq!(Err(DemangleError::Truncated { required: 17 }),
+                   demangle_into(b"_RNvC7mycrateu10mnchen_Dya", &mut buf));
+        // The `ü` would only fit halfway, so it is left out
+        assert_eq!(b"mycrate::m\0", &buf);
+    }
+
+    #[test]
+    fn demangle_into_stops_at_the_maximum_length() {
+        let mangled = ::generate::nested_backrefs(64);
+
+        let mut buf = [0; 64];
+        assert_eq!(Err(DemangleError::TooLong), demangle_into(mangled.as_bytes(), &mut buf));
+        assert_eq!("std::Foo<std::Bar,(&std::Bar,&std::Bar),(&(&std::Bar,&std::Bar),",
+                   str::from_utf8(&buf).unwrap());
+
+        // Exponential output below the maximum is still measured completely
+        let mangled = ::generate::nested_backrefs(12);
+        let required = parse::parse(mangled.as_bytes()).unwrap().demangle().len();
+        assert_eq!(Err(DemangleError::Truncated { required }),
+                   demangle_into(mangled.as_bytes(), &mut buf));
+    }
+
+    #[test]
+    fn demangle_into_reports_invalid_input() {
+        let mut buf = [0; 64];
+        assert_eq!(Err(DemangleError::Invalid { position: 0, reason: "not a Rust symbol" }),
+                   demangle_into(b"_ZN3foo3barE", &mut buf));
+        assert_eq!(Err(DemangleError::Invalid { position: 22, reason: "number is too large" }),
+                   demangle_into(b"_RC99999999999999999999999foo", &mut buf));
+        assert_eq!(Err(DemangleError::Invalid { position: 18, reason: "unexpected trailing characters" }),
+                   demangle_into(b"_RNvC3foo3barC3bazXYZ", &mut buf));
+    }
+}
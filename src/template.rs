@@ -0,0 +1,98 @@
+//! Erasing generic arguments, so that all instances of a generic item map to
+//! the same symbol.
+
+use ast::*;
+use ast_mangle::{mangle, MangleError};
+use fold::{Folder, SharedFolds};
+use alloc::string::String;
+
+impl<'input> Symbol<'input> {
+    /// The symbol with every generic argument replaced by a placeholder, e.g.
+    /// `std::vec::Vec<_>::push` for both `std::vec::Vec<u8>::push` and
+    /// `std::vec::Vec<std::string::String>::push`, along with its mangled
+    /// form. Type arguments become `_`, constants keep their type but lose
+    /// their value and lifetimes are erased.
+    pub fn generic_template(&self) -> Result<(Symbol<'input>, String), MangleError> {
+        let template = EraseGenericArgs::default().fold_symbol(self);
+        let mangled = mangle(&template)?;
+
+        Ok((template, mangled))
+    }
+}
+
+/// Folds each shared subtree once, so that the back-references to it keep
+/// sharing the result.
+#[derive(Default)]
+struct EraseGenericArgs<'input> {
+    shared: SharedFolds<'input>,
+}
+
+impl<'input> Folder<'input> for EraseGenericArgs<'input> {
+    fn fold_generic_arg(&mut self, arg: &GenericArg<'input>) -> GenericArg<'input> {
+        match *arg {
+            GenericArg::Lifetime(_) => {
+                GenericArg::Lifetime(Lifetime { debruijn_index: Base62Number(0) })
+            }
+            GenericArg::Type(_) => GenericArg::Type(Type::BasicType(BasicType::Placeholder)),
+            GenericArg::Const(Const::Value(ref ty, _)) |
+            GenericArg::Const(Const::Placeholder(ref ty)) => {
+                GenericArg::Const(Const::Placeholder(ty.clone()))
+            }
+            GenericArg::Const(Const::Invalid) => GenericArg::Const(Const::Invalid),
+        }
+    }
+
+    fn shared_folds(&mut self) -> Option<&mut SharedFolds<'input>> {
+        Some(&mut self.shared)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use ast_demangle::AstDemangle;
+    use parse::parse;
+
+    fn template(mangled: &str) -> (String, String) {
+        let (template, mangled) = parse(mangled.as_bytes()).unwrap().generic_template().unwrap();
+        (template.demangle(), mangled)
+    }
+
+    #[test]
+    fn instances_share_a_template() {
+        let expected = ("std::vec::Vec<_>::push".to_string(),
+                        "_RNvINtNtC3std3vec3VecpE4push".to_string());
+
+        // std::vec::Vec<u8>::push
+        assert_eq!(template("_RNvINtNtC3std3vec3VechE4push"), expected);
+        // std::vec::Vec<std::string::String>::push
+        assert_eq!(template("_RNvINtNtC3std3vec3VecNtNtB6_6string6StringE4push"), expected);
+    }
+
+    #[test]
+    fn all_kinds_of_generic_args_are_erased() {
+        assert_eq!(template("_RINxC3std3FooL0_Kj3_INtB2_3BarhEE").0,
+                   "std::Foo<'_,{const usize},_>");
+        // <std::Foo<u8>>::bar
+        assert_eq!(template("_RNvMC3stdINtB2_3FoohE3bar").0, "<std::Foo<_>>::bar");
+        // <std::vec::Vec<i16>>::retain<xxx::predicate>
+        assert_eq!(template("_RINxMs89_NyC3std3vecINyNyC3std3vec3VecsE6retainNxC3xxx9predicateE").0,
+                   "<std::vec::Vec<_>>::retain<_>");
+    }
+
+    #[test]
+    fn nested_backrefs_are_erased_once() {
+        use generate::backref_chain;
+
+        // <(std::Foo<T>, (&std::Foo<T>, &std::Foo<T>), (&(..), &(..)), ...)>::bar,
+        // where each element refers back to the previous one twice
+        let instance = |arg: &str| {
+            let mangled = backref_chain("_RNvMC7mycrateT", &format!("INtC3std3Foo{}E", arg), 64,
+                                        |backref| format!("TR{0}R{0}E", backref), "E3bar");
+            parse(mangled.as_bytes()).unwrap().generic_template().unwrap().1
+        };
+
+        assert_eq!(instance("h"), instance("NtC3std3Bar"));
+    }
+}
@@ -0,0 +1,18 @@
+//! The symbols in `demangling_test_data.txt`, for tests that check something
+//! about every one of them.
+
+/// The mangled symbols of all test cases, including the malformed ones.
+pub(crate) fn mangled_symbols() -> impl Iterator<Item = &'static str> {
+    include_str!("demangling_test_data.txt")
+        .lines()
+        .filter(|line| line.starts_with("_R"))
+        .map(|line| line.split_whitespace().next().unwrap())
+}
+
+/// The mangled symbols of the test cases that are expected to demangle.
+pub(crate) fn valid_symbols() -> impl Iterator<Item = &'static str> {
+    include_str!("demangling_test_data.txt")
+        .lines()
+        .filter(|line| line.starts_with("_R") && !line.contains(" !error"))
+        .map(|line| line.split_whitespace().next().unwrap())
+}
@@ -0,0 +1,470 @@
+//! Checks that a symbol is encoded canonically, i.e. the way `ast_mangle`
+//! would encode its AST, rather than as one of the other byte strings that
+//! parse to the same AST.
+
+use ast::*;
+use ast_mangle::mangle;
+use parse::{self, ParseError, Parser, Production, Span};
+use alloc::borrow::Cow;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+#[cfg(feature = "std")]
+use unic_normal::StrNormalForm;
+
+/// A part of a symbol that is not encoded canonically.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Diagnostic {
+    /// Where the offending encoding starts.
+    pub position: usize,
+    pub kind: DiagnosticKind,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DiagnosticKind {
+    /// A decimal, base-62 or hexadecimal number with leading zeros.
+    LeadingZeros,
+    /// A path, type or constant that is spelled out again instead of
+    /// referring back to the equal one at `earlier`.
+    UncompressedRepeat { production: Production, earlier: usize },
+    /// A punycode identifier that only contains ASCII.
+    AsciiPunycode,
+    /// A punycode identifier that is not in Unicode Normalization Form C.
+    NotNfc,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at position {}: ", self.position)?;
+
+        match self.kind {
+            DiagnosticKind::LeadingZeros => {
+                write!(f, "number has leading zeros")
+            }
+            DiagnosticKind::UncompressedRepeat { production, earlier } => {
+                write!(f, "{} repeats the one at position {} instead of referring back to it",
+                       production.name(),
+                       earlier)
+            }
+            DiagnosticKind::AsciiPunycode => {
+                write!(f, "punycode identifier only contains ASCII characters")
+            }
+            DiagnosticKind::NotNfc => {
+                write!(f, "identifier is not in Unicode Normalization Form C")
+            }
+        }
+    }
+}
+
+/// Parses `input` and returns the places where it deviates from the
+/// canonical encoding, in the order they occur. The symbol is canonical if
+/// there are none. Identifiers are only checked for NFC with the `std`
+/// feature.
+pub fn validate(input: &[u8]) -> Result<Vec<Diagnostic>, ParseError> {
+    parse::parse_with_context(input)?;
+
+    let (_, spans) = parse::parse_with_literal_spans(input).expect("the symbol parsed before");
+    let literal = literal_spans(&spans);
+    let zeros = leading_zeros(input, &literal);
+
+    let mut diagnostics = zeros.iter()
+        .map(|zeros| {
+            Diagnostic {
+                position: zeros.start,
+                kind: DiagnosticKind::LeadingZeros,
+            }
+        })
+        .collect();
+    check_punycode(input, &literal, &mut diagnostics);
+    check_repeats(input, &spans, &zeros, &mut diagnostics);
+
+    diagnostics.sort_by_key(|diagnostic| diagnostic.position);
+    Ok(diagnostics)
+}
+
+/// Re-encodes a valid symbol canonically: without leading zeros or
+/// punycode for ASCII identifiers, and with every repeated path, type and
+/// constant replaced by a back-reference. Identifiers are not normalized,
+/// so a symbol with `DiagnosticKind::NotNfc` keeps it.
+pub fn canonicalize(input: &[u8]) -> Result<String, String> {
+    let symbol = parse::parse_with_context(input).map_err(|e| e.to_string())?;
+    mangle(&symbol).map_err(|e| e.to_string())
+}
+
+/// The spans of the productions that are spelled out in the input, leaving
+/// out the ones recorded for the targets of back-references.
+fn literal_spans(spans: &[Span]) -> Vec<&Span> {
+    let mut literal = Vec::new();
+    let mut inside_backref = None;
+
+    for span in spans {
+        if let Some(depth) = inside_backref {
+            if span.depth > depth {
+                continue;
+            }
+            inside_backref = None;
+        }
+
+        if span.production == Production::Backref {
+            inside_backref = Some(span.depth);
+        }
+
+        literal.push(span);
+    }
+
+    literal
+}
+
+/// The leading zeros of every number that has them.
+fn leading_zeros(input: &[u8], spans: &[&Span]) -> Vec<Range<usize>> {
+    let mut zeros = Vec::new();
+
+    for (index, span) in spans.iter().enumerate() {
+        let (start, terminated) = match span.production {
+            Production::UIdent if input[span.start] == b'u' => (span.start + 1, false),
+            Production::UIdent => (span.start, false),
+            // The number follows the tag
+            Production::Disambiguator |
+            Production::Lifetime |
+            Production::Binder |
+            Production::Backref => (span.start + 1, true),
+            // The value follows the type, which is the first nested span
+            Production::Const => {
+                let ty = spans[index + 1..]
+                    .iter()
+                    .find(|ty| ty.depth == span.depth + 1)
+                    .expect("a constant starts with its type");
+
+                if input[ty.end] == b'p' {
+                    continue;
+                }
+
+                (ty.end, true)
+            }
+            _ => continue,
+        };
+
+        // Terminated numbers end in `_`, so `0_` has no leading zero
+        let has_more_digits = |pos: usize| {
+            let next = input.get(pos + 1).cloned().unwrap_or(b'_');
+            if terminated { next != b'_' } else { next.is_ascii_digit() }
+        };
+
+        let mut end = start;
+        while input[end] == b'0' && has_more_digits(end) {
+            end += 1;
+        }
+
+        if end > start {
+            zeros.push(start..end);
+        }
+    }
+
+    zeros
+}
+
+fn check_punycode(input: &[u8], spans: &[&Span], diagnostics: &mut Vec<Diagnostic>) {
+    for span in spans {
+        if span.production != Production::UIdent || input[span.start] != b'u' {
+            continue;
+        }
+
+        let UIdent(ident) = Parser::at(input, span.start)
+            .parse_uident()
+            .expect("the symbol parsed before");
+
+        let kind = if ident.is_ascii() {
+            DiagnosticKind::AsciiPunycode
+        } else if !is_nfc(&ident) {
+            DiagnosticKind::NotNfc
+        } else {
+            continue;
+        };
+
+        diagnostics.push(Diagnostic {
+            position: span.start,
+            kind,
+        });
+    }
+}
+
+#[cfg(feature = "std")]
+fn is_nfc(ident: &str) -> bool {
+    ident.nfc().eq(ident.chars())
+}
+
+#[cfg(not(feature = "std"))]
+fn is_nfc(_: &str) -> bool {
+    true
+}
+
+/// A byte of a path, type or constant, or something nested in it that is
+/// compared by value rather than by its encoding.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Token<'input> {
+    Byte(u8),
+    Ident(Cow<'input, str>),
+    Node(usize),
+}
+
+/// Numbers the distinct paths, types and constants of a symbol. Each one is
+/// keyed on its bytes with the nodes nested in it, including back-references,
+/// replaced by their numbers, so equal nodes get the same number without
+/// comparing whole subtrees.
+struct Interner<'a, 'input: 'a> {
+    input: &'input [u8],
+    spans: &'a [Span],
+    children: Vec<Vec<usize>>,
+    zeros: BTreeSet<usize>,
+    node_ids: Vec<Option<usize>>,
+    /// The number of the node parsed at each position, by production.
+    at: BTreeMap<(Production, usize), usize>,
+    interned: BTreeMap<(Production, Vec<Token<'input>>), usize>,
+}
+
+impl<'a, 'input> Interner<'a, 'input> {
+    fn new(input: &'input [u8], spans: &'a [Span], zeros: &[Range<usize>]) -> Self {
+        let mut interner = Interner {
+            input,
+            spans,
+            children: vec![Vec::new(); spans.len()],
+            zeros: zeros.iter().cloned().flatten().collect(),
+            node_ids: vec![None; spans.len()],
+            at: BTreeMap::new(),
+            interned: BTreeMap::new(),
+        };
+
+        // Closing the spans in the order they end numbers every node after
+        // the ones nested in it and the ones it can refer back to
+        let mut open: Vec<usize> = Vec::new();
+        for index in 0..spans.len() + 1 {
+            let depth = spans.get(index).map_or(0, |span| span.depth);
+
+            while let Some(&last) = open.last() {
+                if spans[last].depth < depth {
+                    break;
+                }
+                open.pop();
+
+                let parent = open.last().map(|&parent| spans[parent].production);
+                if parent != Some(Production::Backref) {
+                    interner.number(last);
+                }
+            }
+
+            if index < spans.len() {
+                if let Some(&parent) = open.last() {
+                    interner.children[parent].push(index);
+                }
+                open.push(index);
+            }
+        }
+
+        interner
+    }
+
+    fn number(&mut self, index: usize) {
+        let span = &self.spans[index];
+
+        let id = match span.production {
+            Production::Path | Production::Type | Production::Const => {
+                let mut tokens = Vec::new();
+                self.tokens(index, &mut tokens);
+                self.intern(span.production, tokens)
+            }
+            Production::Backref => {
+                let target = &self.spans[self.children[index][0]];
+                self.target(target)
+            }
+            _ => return,
+        };
+
+        self.at.insert((span.production, span.start), id);
+        self.node_ids[index] = Some(id);
+    }
+
+    /// The number of the node a back-reference refers to. A type can refer
+    /// back to a path, which is then wrapped in a type.
+    fn target(&mut self, target: &Span) -> usize {
+        let known = self.at.get(&(target.production, target.start))
+            .or_else(|| self.at.get(&(Production::Backref, target.start)))
+            .cloned();
+
+        if let Some(id) = known {
+            return id;
+        }
+
+        let tokens = match self.at.get(&(Production::Path, target.start)) {
+            Some(&path) if target.production == Production::Type => vec![Token::Node(path)],
+            _ => self.bytes(target.start..target.end).collect(),
+        };
+        self.intern(target.production, tokens)
+    }
+
+    fn tokens(&self, index: usize, tokens: &mut Vec<Token<'input>>) {
+        let span = &self.spans[index];
+        let mut pos = span.start;
+
+        for &child in &self.children[index] {
+            let child_span = &self.spans[child];
+            tokens.extend(self.bytes(pos..child_span.start));
+
+            if let Some(id) = self.node_ids[child] {
+                tokens.push(Token::Node(id));
+            } else if child_span.production == Production::UIdent {
+                let UIdent(ident) = Parser::at(self.input, child_span.start)
+                    .parse_uident()
+                    .expect("the symbol parsed before");
+                tokens.push(Token::Ident(ident));
+            } else {
+                self.tokens(child, tokens);
+            }
+
+            pos = child_span.end;
+        }
+
+        tokens.extend(self.bytes(pos..span.end));
+    }
+
+    /// The bytes in `range`, without leading zeros.
+    fn bytes<'b>(&'b self, range: Range<usize>) -> impl Iterator<Item = Token<'input>> + 'b {
+        range.filter(move |pos| !self.zeros.contains(pos))
+            .map(move |pos| Token::Byte(self.input[pos]))
+    }
+
+    fn intern(&mut self, production: Production, tokens: Vec<Token<'input>>) -> usize {
+        let next = self.interned.len();
+        *self.interned.entry((production, tokens)).or_insert(next)
+    }
+}
+
+/// Flags the paths, types and constants that are equal to one that ended
+/// before them, which the mangler would have referred back to. Basic types
+/// and the types of constants are never replaced by back-references.
+fn check_repeats(input: &[u8],
+                 spans: &[Span],
+                 zeros: &[Range<usize>],
+                 diagnostics: &mut Vec<Diagnostic>) {
+    let interner = Interner::new(input, spans, zeros);
+    let mut first = BTreeMap::new();
+    let mut const_types = BTreeSet::new();
+    // Repeats nested inside a repeat are not reported separately
+    let mut repeat_end = 0;
+
+    for (index, span) in spans.iter().enumerate() {
+        let id = match (span.production, interner.node_ids[index]) {
+            (Production::Backref, _) | (_, None) => continue,
+            (Production::Type, _) if span.end - span.start == 1 => continue,
+            (_, Some(id)) => id,
+        };
+
+        if span.production == Production::Const {
+            const_types.insert(interner.children[index][0]);
+        }
+
+        let earlier = *first.entry(id).or_insert(index);
+
+        if earlier != index && span.start >= repeat_end && !const_types.contains(&index) {
+            diagnostics.push(Diagnostic {
+                position: span.start,
+                kind: DiagnosticKind::UncompressedRepeat {
+                    production: span.production,
+                    earlier: spans[earlier].start,
+                },
+            });
+            repeat_end = span.end;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::{String, ToString};
+    use parse::parse;
+
+    fn diagnostics(symbol: &str) -> Vec<String> {
+        validate(symbol.as_bytes())
+            .unwrap()
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn canonicalizing_keeps_the_symbol() {
+        assert_eq!(canonicalize(b"_RINxC3std3fooNtC3std3BarNtC3std03BarE").unwrap(),
+                   "_RINxC3std3fooNtB2_3BarBb_E");
+        assert_eq!(canonicalize(b"_RNvCs00_3foou4bar_").unwrap(), "_RNvCs0_3foo3bar");
+
+        for mangled in ::test_data::valid_symbols() {
+            let canonical = canonicalize(mangled.as_bytes()).unwrap();
+
+            assert_eq!(parse(canonical.as_bytes()), parse(mangled.as_bytes()));
+            assert_eq!(diagnostics(&canonical), Vec::<String>::new(), "{}", canonical);
+            assert_eq!(canonicalize(canonical.as_bytes()).unwrap(), canonical);
+        }
+    }
+
+    #[test]
+    fn leading_zeros_are_flagged() {
+        assert_eq!(diagnostics("_RNvC03foo3bar"),
+                   vec!["at position 5: number has leading zeros"]);
+        assert_eq!(diagnostics("_RNvCs00_3foo3bar"),
+                   vec!["at position 6: number has leading zeros"]);
+        assert_eq!(diagnostics("_RINxC3std3FooKj00a_E"),
+                   vec!["at position 16: number has leading zeros"]);
+        assert_eq!(diagnostics("_RINxC3std3FooKj0_E"), Vec::<String>::new());
+        assert_eq!(diagnostics("_RNCNvC3foo4main0"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn uncompressed_repeats_are_flagged() {
+        // std::foo<std::Bar, std::Bar>
+        assert_eq!(diagnostics("_RINxC3std3fooNtC3std3BarNtC3std3BarE"),
+                   vec!["at position 16: <path> repeats the one at position 5 instead of \
+                         referring back to it",
+                        "at position 25: <type> repeats the one at position 14 instead of \
+                         referring back to it"]);
+        assert_eq!(diagnostics("_RINxC3std3fooNtB2_3BarBb_E"), Vec::<String>::new());
+
+        // Leading zeros and punycode do not make the repeat any different
+        assert_eq!(diagnostics("_RINxC3std3fooNtC3std3BarNtC03stdu4Bar_E"),
+                   vec!["at position 16: <path> repeats the one at position 5 instead of \
+                         referring back to it",
+                        "at position 25: <type> repeats the one at position 14 instead of \
+                         referring back to it",
+                        "at position 28: number has leading zeros",
+                        "at position 33: punycode identifier only contains ASCII characters"]);
+    }
+
+    #[test]
+    fn nested_backrefs_are_not_expanded() {
+        // Each level is a tuple `(&T, &T)` that spells out `&T` twice
+        let mangled = ::generate::nested_backrefs(64);
+        let diagnostics = validate(mangled.as_bytes()).unwrap();
+
+        assert_eq!(diagnostics.len(), 64);
+        assert!(diagnostics.iter().all(|diagnostic| {
+            matches!(diagnostic.kind,
+                     DiagnosticKind::UncompressedRepeat { production: Production::Type, .. })
+        }));
+    }
+
+    #[test]
+    fn punycode_is_only_used_for_normalized_unicode() {
+        assert_eq!(diagnostics("_RNvC3foou4bar_"),
+                   vec!["at position 9: punycode identifier only contains ASCII characters"]);
+        assert_eq!(diagnostics("_RNvC3foou7caf_dma"), Vec::<String>::new());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn identifiers_are_in_nfc() {
+        // "cafe" followed by a combining acute accent
+        assert_eq!(diagnostics("_RNvC3foou8cafe_yvc"),
+                   vec!["at position 9: identifier is not in Unicode Normalization Form C"]);
+    }
+}
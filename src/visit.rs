@@ -0,0 +1,339 @@
+//! Read-only traversal of the AST. The methods of `Visitor` walk all
+//! children of a node by default, via the `walk_*` functions, so that an
+//! analysis only needs to override the methods for the nodes it cares about.
+
+use ast::*;
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+
+pub trait Visitor<'input> {
+    fn visit_symbol(&mut self, symbol: &Symbol<'input>) {
+        walk_symbol(self, symbol);
+    }
+
+    fn visit_path(&mut self, path: &Path<'input>) {
+        walk_path(self, path);
+    }
+
+    fn visit_impl_path(&mut self, impl_path: &ImplPath<'input>) {
+        walk_impl_path(self, impl_path);
+    }
+
+    fn visit_ident(&mut self, _ident: &Ident<'input>) {}
+
+    fn visit_generic_arg(&mut self, arg: &GenericArg<'input>) {
+        walk_generic_arg(self, arg);
+    }
+
+    fn visit_lifetime(&mut self, _lifetime: &Lifetime) {}
+
+    fn visit_binder(&mut self, _binder: &Binder) {}
+
+    fn visit_type(&mut self, ty: &Type<'input>) {
+        walk_type(self, ty);
+    }
+
+    fn visit_fn_sig(&mut self, fn_sig: &FnSig<'input>) {
+        walk_fn_sig(self, fn_sig);
+    }
+
+    fn visit_dyn_bounds(&mut self, bounds: &DynBounds<'input>) {
+        walk_dyn_bounds(self, bounds);
+    }
+
+    fn visit_dyn_trait(&mut self, tr: &DynTrait<'input>) {
+        walk_dyn_trait(self, tr);
+    }
+
+    fn visit_const(&mut self, k: &Const<'input>) {
+        walk_const(self, k);
+    }
+
+    /// Where the shared subtrees that have been visited are remembered.
+    /// With the default, `None`, a shared subtree is visited once for every
+    /// reference to it; return a `SharedNodes` to visit it only once.
+    fn shared_nodes(&mut self) -> Option<&mut SharedNodes> {
+        None
+    }
+
+    /// Called for the children behind an `Arc`, which the parser shares
+    /// between all back-references to the same node.
+    fn visit_shared_path(&mut self, path: &Arc<Path<'input>>) {
+        if first_visit(self, path) {
+            self.visit_path(path);
+        }
+    }
+
+    fn visit_shared_type(&mut self, ty: &Arc<Type<'input>>) {
+        if first_visit(self, ty) {
+            self.visit_type(ty);
+        }
+    }
+
+    fn visit_shared_const(&mut self, k: &Arc<Const<'input>>) {
+        if first_visit(self, k) {
+            self.visit_const(k);
+        }
+    }
+
+    fn visit_shared_fn_sig(&mut self, fn_sig: &Arc<FnSig<'input>>) {
+        if first_visit(self, fn_sig) {
+            self.visit_fn_sig(fn_sig);
+        }
+    }
+
+    fn visit_shared_dyn_bounds(&mut self, bounds: &Arc<DynBounds<'input>>) {
+        if first_visit(self, bounds) {
+            self.visit_dyn_bounds(bounds);
+        }
+    }
+}
+
+/// The `Arc`s a visitor has been through, by address.
+#[derive(Default, Debug)]
+pub struct SharedNodes(BTreeSet<usize>);
+
+impl SharedNodes {
+    /// Whether this is the first time `node` is visited.
+    pub fn first_visit<T>(&mut self, node: &Arc<T>) -> bool {
+        self.0.insert(Arc::as_ptr(node) as usize)
+    }
+}
+
+fn first_visit<'input, V, T>(visitor: &mut V, node: &Arc<T>) -> bool
+    where V: Visitor<'input> + ?Sized
+{
+    match visitor.shared_nodes() {
+        Some(shared) => shared.first_visit(node),
+        None => true,
+    }
+}
+
+pub fn walk_symbol<'input, V>(visitor: &mut V, symbol: &Symbol<'input>)
+    where V: Visitor<'input> + ?Sized
+{
+    visitor.visit_path(&symbol.path);
+
+    if let Some(ref instantiating_crate) = symbol.instantiating_crate {
+        visitor.visit_path(instantiating_crate);
+    }
+}
+
+pub fn walk_path<'input, V>(visitor: &mut V, path: &Path<'input>)
+    where V: Visitor<'input> + ?Sized
+{
+    match *path {
+        Path::CrateRoot { ref id } => {
+            visitor.visit_ident(id);
+        }
+        Path::InherentImpl { ref impl_path, ref self_type } => {
+            visitor.visit_impl_path(impl_path);
+            visitor.visit_type(self_type);
+        }
+        Path::TraitImpl { ref impl_path, ref self_type, ref trait_name } => {
+            visitor.visit_impl_path(impl_path);
+            visitor.visit_type(self_type);
+            visitor.visit_shared_path(trait_name);
+        }
+        Path::TraitDef { ref self_type, ref trait_name } => {
+            visitor.visit_type(self_type);
+            visitor.visit_shared_path(trait_name);
+        }
+        Path::Nested { ns: _, ref inner, ref ident } => {
+            visitor.visit_shared_path(inner);
+
+            if let Some(ref ident) = *ident {
+                visitor.visit_ident(ident);
+            }
+        }
+        Path::Generic { ref inner, ref args } => {
+            visitor.visit_shared_path(inner);
+
+            for arg in args {
+                visitor.visit_generic_arg(arg);
+            }
+        }
+        Path::Invalid => {}
+    }
+}
+
+pub fn walk_impl_path<'input, V>(visitor: &mut V, impl_path: &ImplPath<'input>)
+    where V: Visitor<'input> + ?Sized
+{
+    visitor.visit_shared_path(&impl_path.path);
+}
+
+pub fn walk_generic_arg<'input, V>(visitor: &mut V, arg: &GenericArg<'input>)
+    where V: Visitor<'input> + ?Sized
+{
+    match *arg {
+        GenericArg::Lifetime(ref lt) => visitor.visit_lifetime(lt),
+        GenericArg::Type(ref ty) => visitor.visit_type(ty),
+        GenericArg::Const(ref k) => visitor.visit_const(k),
+    }
+}
+
+pub fn walk_type<'input, V>(visitor: &mut V, ty: &Type<'input>)
+    where V: Visitor<'input> + ?Sized
+{
+    match *ty {
+        Type::BasicType(_) |
+        Type::Invalid => {}
+        Type::Array(ref inner, ref len) => {
+            visitor.visit_shared_type(inner);
+            visitor.visit_shared_const(len);
+        }
+        Type::Slice(ref inner) |
+        Type::RawPtrConst(ref inner) |
+        Type::RawPtrMut(ref inner) => {
+            visitor.visit_shared_type(inner);
+        }
+        Type::Named(ref path) => {
+            visitor.visit_shared_path(path);
+        }
+        Type::Tuple(ref components) => {
+            for component in components {
+                visitor.visit_type(component);
+            }
+        }
+        Type::Ref(ref lt, ref inner) |
+        Type::RefMut(ref lt, ref inner) => {
+            if let Some(ref lt) = *lt {
+                visitor.visit_lifetime(lt);
+            }
+            visitor.visit_shared_type(inner);
+        }
+        Type::Fn(ref fn_sig) => {
+            visitor.visit_shared_fn_sig(fn_sig);
+        }
+        Type::DynTrait(ref bounds, ref lt) => {
+            visitor.visit_shared_dyn_bounds(bounds);
+            visitor.visit_lifetime(lt);
+        }
+    }
+}
+
+pub fn walk_fn_sig<'input, V>(visitor: &mut V, fn_sig: &FnSig<'input>)
+    where V: Visitor<'input> + ?Sized
+{
+    visitor.visit_binder(&fn_sig.binder);
+
+    for param_type in &fn_sig.param_types {
+        visitor.visit_type(param_type);
+    }
+
+    visitor.visit_type(&fn_sig.return_type);
+}
+
+pub fn walk_dyn_bounds<'input, V>(visitor: &mut V, bounds: &DynBounds<'input>)
+    where V: Visitor<'input> + ?Sized
+{
+    visitor.visit_binder(&bounds.binder);
+
+    for tr in &bounds.traits {
+        visitor.visit_dyn_trait(tr);
+    }
+}
+
+pub fn walk_dyn_trait<'input, V>(visitor: &mut V, tr: &DynTrait<'input>)
+    where V: Visitor<'input> + ?Sized
+{
+    visitor.visit_path(&tr.path);
+
+    for binding in &tr.assoc_type_bindings {
+        visitor.visit_type(&binding.ty);
+    }
+}
+
+pub fn walk_const<'input, V>(visitor: &mut V, k: &Const<'input>)
+    where V: Visitor<'input> + ?Sized
+{
+    match *k {
+        Const::Value(ref ty, _) |
+        Const::Placeholder(ref ty) => visitor.visit_type(ty),
+        Const::Invalid => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+    use parse::parse;
+
+    #[derive(Default)]
+    struct CrateRoots {
+        names: Vec<String>,
+        shared: Option<SharedNodes>,
+    }
+
+    impl<'input> Visitor<'input> for CrateRoots {
+        fn visit_path(&mut self, path: &Path<'input>) {
+            if let Path::CrateRoot { ref id } = *path {
+                self.names.push(id.u_ident.0.to_string());
+            }
+            walk_path(self, path);
+        }
+
+        fn shared_nodes(&mut self) -> Option<&mut SharedNodes> {
+            self.shared.as_mut()
+        }
+    }
+
+    #[test]
+    fn shared_subtrees_can_be_visited_once() {
+        // std::foo<std::Bar, &std::Bar, &std::Bar>
+        let symbol = parse(b"_RINxC3std3fooNtB2_3BarRBb_RBb_E").unwrap();
+
+        let mut every_time = CrateRoots::default();
+        every_time.visit_symbol(&symbol);
+        assert_eq!(every_time.names, ["std", "std", "std", "std"]);
+
+        // All four are the crate root the parser shared with `B2_`
+        let mut once = CrateRoots { shared: Some(SharedNodes::default()), ..Default::default() };
+        once.visit_symbol(&symbol);
+        assert_eq!(once.names, ["std"]);
+    }
+
+    #[test]
+    fn all_children_are_visited() {
+        #[derive(Default)]
+        struct Count {
+            lifetimes: usize,
+            consts: usize,
+            fn_sigs: usize,
+            dyn_traits: usize,
+        }
+
+        impl<'input> Visitor<'input> for Count {
+            fn visit_lifetime(&mut self, _: &Lifetime) {
+                self.lifetimes += 1;
+            }
+
+            fn visit_const(&mut self, k: &Const<'input>) {
+                self.consts += 1;
+                walk_const(self, k);
+            }
+
+            fn visit_fn_sig(&mut self, fn_sig: &FnSig<'input>) {
+                self.fn_sigs += 1;
+                walk_fn_sig(self, fn_sig);
+            }
+
+            fn visit_dyn_trait(&mut self, tr: &DynTrait<'input>) {
+                self.dyn_traits += 1;
+                walk_dyn_trait(self, tr);
+            }
+        }
+
+        // std::Foo<[fn(&u8); 3], dyn std::Iterator<Item=u8>, '_>
+        let symbol = parse(b"_RINxC3std3FooAFG0_RL0_hEuj3_DG_NtB2_8Iteratorp4ItemhEL_L_E")
+            .unwrap();
+
+        let mut count = Count::default();
+        count.visit_symbol(&symbol);
+
+        assert_eq!((count.lifetimes, count.consts, count.fn_sigs, count.dyn_traits), (3, 1, 1, 1));
+    }
+}